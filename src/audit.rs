@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::events::{Event, Subscriber};
+use crate::reason::Reason;
+use crate::ChargeBehaviour;
+use crate::{clock, state};
+
+/// Rotate the audit log once it grows beyond this many bytes.
+const MAX_SIZE: u64 = 1024 * 1024;
+/// Number of rotated audit logs to keep.
+const KEEP: usize = 3;
+
+/// Append-only log of every write to charge_behaviour.
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            path: dir.join("audit.log"),
+        }
+    }
+
+    pub fn record(
+        &self,
+        old: ChargeBehaviour,
+        new: ChargeBehaviour,
+        cap: i8,
+        reason: Reason,
+    ) -> Result<(), anyhow::Error> {
+        self.rotate()?;
+        state::append_line(
+            &self.path,
+            &format!(
+                "{} old={old} new={new} capacity={cap} kind={} reason=\"{reason}\"",
+                clock::format_timestamp(clock::now()),
+                reason.kind()
+            ),
+        )
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut p = self.path.clone().into_os_string();
+        p.push(format!(".{n}"));
+        p.into()
+    }
+
+    fn rotate(&self) -> Result<(), anyhow::Error> {
+        match fs::metadata(&self.path) {
+            Ok(m) if m.len() >= MAX_SIZE => {}
+            _ => return Ok(()),
+        }
+        for n in (1..KEEP).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                fs::rename(from, self.rotated(n + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        Ok(())
+    }
+}
+
+impl Subscriber for AuditLog {
+    fn handle(&mut self, event: &Event) {
+        if let Event::TransitionApplied {
+            old,
+            new,
+            capacity,
+            reason,
+        } = event
+        {
+            if let Err(e) = self.record(*old, *new, *capacity, *reason) {
+                warn!("Could not write audit log: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::audit::{AuditLog, MAX_SIZE};
+    use crate::reason::Reason;
+    use crate::testdir::TestDir;
+    use crate::ChargeBehaviour;
+
+    #[test]
+    fn appends_and_rotates() {
+        let dir = TestDir::new("audit");
+        let log = AuditLog::new(&dir);
+
+        log.record(
+            ChargeBehaviour::Auto,
+            ChargeBehaviour::InhibitCharge,
+            80,
+            Reason::WithinThresholds,
+        )
+        .unwrap();
+        let s = fs::read_to_string(dir.join("audit.log")).unwrap();
+        assert!(
+            s.ends_with("old=auto new=inhibit-charge capacity=80 kind=within_thresholds reason=\"within thresholds\"\n")
+        );
+
+        fs::write(dir.join("audit.log"), vec![b'x'; MAX_SIZE as usize]).unwrap();
+        log.record(
+            ChargeBehaviour::InhibitCharge,
+            ChargeBehaviour::Auto,
+            69,
+            Reason::BelowLow,
+        )
+        .unwrap();
+        assert_eq!(
+            MAX_SIZE,
+            fs::metadata(dir.join("audit.log.1")).unwrap().len()
+        );
+        assert_eq!(
+            1,
+            fs::read_to_string(dir.join("audit.log"))
+                .unwrap()
+                .lines()
+                .count()
+        );
+    }
+}
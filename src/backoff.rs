@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use log::info;
+
+use crate::ChargeBehaviour;
+
+/// Consecutive rejected writes after which a behaviour is given up on.
+const MAX_FAILURES: u32 = 3;
+
+/// The next-best behaviour to use when `b` is rejected.
+fn fallback(b: ChargeBehaviour) -> Option<ChargeBehaviour> {
+    match b {
+        ChargeBehaviour::ForceDischarge => Some(ChargeBehaviour::InhibitCharge),
+        ChargeBehaviour::InhibitCharge => Some(ChargeBehaviour::Auto),
+        ChargeBehaviour::Auto => None,
+    }
+}
+
+/// The state writes are rejected in. The lid isn't part of it, it isn't
+/// exposed by the power_supply class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    pub ac_online: Option<bool>,
+    /// Negotiated power of the adapter, which tells chargers apart.
+    pub adapter_watts: Option<u32>,
+}
+
+/// Remembers behaviours the firmware keeps rejecting in the current context,
+/// so they are only retried once it changes.
+#[derive(Debug, Default)]
+pub struct WriteBackoff {
+    context: Option<Context>,
+    failures: HashMap<ChargeBehaviour, u32>,
+}
+
+impl WriteBackoff {
+    pub fn set_context(&mut self, context: Context) {
+        if Some(context) != self.context {
+            if self.failures.values().any(|f| *f >= MAX_FAILURES) {
+                info!(
+                    "AC state or adapter changed, retrying previously rejected charge behaviours"
+                );
+            }
+            self.failures.clear();
+            self.context = Some(context);
+        }
+    }
+
+    fn rejected(&self, b: ChargeBehaviour) -> bool {
+        self.failures.get(&b).is_some_and(|f| *f >= MAX_FAILURES)
+    }
+
+    /// The behaviour to write instead of `wanted`, skipping rejected ones.
+    pub fn choose(&self, wanted: ChargeBehaviour) -> ChargeBehaviour {
+        let mut b = wanted;
+        while self.rejected(b) {
+            match fallback(b) {
+                Some(f) => b = f,
+                None => break,
+            }
+        }
+        b
+    }
+
+    /// Record a rejected write, returns true once `b` is given up on.
+    pub fn record_failure(&mut self, b: ChargeBehaviour) -> bool {
+        let f = self.failures.entry(b).or_insert(0);
+        *f += 1;
+        *f == MAX_FAILURES
+    }
+
+    pub fn record_success(&mut self, b: ChargeBehaviour) {
+        self.failures.remove(&b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backoff::{Context, WriteBackoff, MAX_FAILURES};
+    use crate::ChargeBehaviour;
+
+    fn on(ac_online: Option<bool>, adapter_watts: Option<u32>) -> Context {
+        Context {
+            ac_online,
+            adapter_watts,
+        }
+    }
+
+    #[test]
+    fn repeatedly_rejected_behaviour_falls_back_until_context_changes() {
+        let mut b = WriteBackoff::default();
+        b.set_context(on(Some(true), Some(96)));
+        let fd = ChargeBehaviour::ForceDischarge;
+
+        for _ in 1..MAX_FAILURES {
+            assert!(!b.record_failure(fd));
+            assert_eq!(fd, b.choose(fd));
+        }
+        assert!(b.record_failure(fd));
+        assert_eq!(ChargeBehaviour::InhibitCharge, b.choose(fd));
+
+        b.set_context(on(Some(true), Some(96)));
+        assert_eq!(ChargeBehaviour::InhibitCharge, b.choose(fd));
+        b.set_context(on(Some(false), None));
+        assert_eq!(fd, b.choose(fd));
+
+        // Another charger is another context too.
+        b.set_context(on(Some(true), Some(96)));
+        for _ in 0..MAX_FAILURES {
+            b.record_failure(fd);
+        }
+        assert_eq!(ChargeBehaviour::InhibitCharge, b.choose(fd));
+        b.set_context(on(Some(true), Some(30)));
+        assert_eq!(fd, b.choose(fd));
+    }
+
+    #[test]
+    fn fallback_chain_and_success_reset() {
+        let mut b = WriteBackoff::default();
+        for _ in 0..MAX_FAILURES {
+            b.record_failure(ChargeBehaviour::ForceDischarge);
+            b.record_failure(ChargeBehaviour::InhibitCharge);
+            b.record_failure(ChargeBehaviour::Auto);
+        }
+        assert_eq!(
+            ChargeBehaviour::Auto,
+            b.choose(ChargeBehaviour::ForceDischarge)
+        );
+
+        b.record_success(ChargeBehaviour::InhibitCharge);
+        assert_eq!(
+            ChargeBehaviour::InhibitCharge,
+            b.choose(ChargeBehaviour::ForceDischarge)
+        );
+    }
+}
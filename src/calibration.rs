@@ -0,0 +1,177 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ChargeBehaviour;
+
+/// Longest the charge to full may take, in seconds, after which the cycle is
+/// given up, e.g. when a firmware limit or the fuel gauge keeps the battery
+/// from ever being reported full.
+pub const CHARGE_TIMEOUT: u64 = 12 * 60 * 60;
+
+fn state_path(dir: &Path) -> PathBuf {
+    dir.join("calibration.json")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Charging to full.
+    Charging,
+    /// Discharging down to the floor.
+    Discharging,
+}
+
+/// Progress of the periodic calibration cycle, kept across restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Calibration {
+    /// When the last cycle finished (or calibration was first enabled), in
+    /// seconds since the epoch.
+    pub last: u64,
+    /// The cycle in progress, if any.
+    pub phase: Option<Phase>,
+    /// When the cycle in progress started charging.
+    #[serde(default)]
+    pub started: Option<u64>,
+}
+
+impl Calibration {
+    /// Load the saved state, starting the clock now if there is none.
+    pub fn load(dir: &Path, now: u64) -> Result<Self, anyhow::Error> {
+        match fs::read_to_string(state_path(dir)) {
+            Ok(s) => Ok(serde_json::from_str(&s)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let c = Self {
+                    last: now,
+                    phase: None,
+                    started: None,
+                };
+                c.save(dir)?;
+                Ok(c)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), anyhow::Error> {
+        fs::create_dir_all(dir)?;
+        fs::write(state_path(dir), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Advance the cycle, returning the behaviour it needs, if any. A cycle
+    /// starts `every` seconds after the last one, charges until `full`, then
+    /// discharges to `floor`. A cycle not full within [`CHARGE_TIMEOUT`] ends
+    /// as if it had finished, so the next one is `every` seconds later.
+    pub fn step(
+        &mut self,
+        now: u64,
+        cap: i8,
+        full: bool,
+        every: u64,
+        floor: i8,
+    ) -> Option<ChargeBehaviour> {
+        loop {
+            match self.phase {
+                None if now.saturating_sub(self.last) >= every => {
+                    self.phase = Some(Phase::Charging);
+                    self.started = Some(now);
+                }
+                None => return None,
+                Some(Phase::Charging) if full => self.phase = Some(Phase::Discharging),
+                Some(Phase::Charging) => {
+                    // State saved before there was a deadline starts it now.
+                    let started = *self.started.get_or_insert(now);
+                    if now.saturating_sub(started) < CHARGE_TIMEOUT {
+                        return Some(ChargeBehaviour::Auto);
+                    }
+                    self.finish(now);
+                    return None;
+                }
+                Some(Phase::Discharging) if cap <= floor => {
+                    self.finish(now);
+                    return None;
+                }
+                Some(Phase::Discharging) => return Some(ChargeBehaviour::ForceDischarge),
+            }
+        }
+    }
+
+    /// Something else is deciding for now, so the charge deadline starts
+    /// over once the cycle continues. Returns whether that changed the state.
+    pub fn postpone(&mut self) -> bool {
+        self.phase == Some(Phase::Charging) && self.started.take().is_some()
+    }
+
+    fn finish(&mut self, now: u64) {
+        self.phase = None;
+        self.started = None;
+        self.last = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calibration::{Calibration, Phase, CHARGE_TIMEOUT};
+    use crate::testdir::TestDir;
+    use crate::ChargeBehaviour;
+
+    const WEEK: u64 = 7 * 24 * 60 * 60;
+
+    #[test]
+    fn full_cycle() {
+        let mut c = Calibration {
+            last: 0,
+            phase: None,
+            started: None,
+        };
+        let auto = Some(ChargeBehaviour::Auto);
+        let fd = Some(ChargeBehaviour::ForceDischarge);
+
+        assert_eq!(None, c.step(WEEK - 1, 80, false, WEEK, 20));
+        assert_eq!(auto, c.step(WEEK, 80, false, WEEK, 20));
+        assert_eq!(auto, c.step(WEEK + 1, 99, false, WEEK, 20));
+        assert_eq!(fd, c.step(WEEK + 2, 100, true, WEEK, 20));
+        assert_eq!(Some(Phase::Discharging), c.phase);
+        assert_eq!(fd, c.step(WEEK + 3, 21, false, WEEK, 20));
+        assert_eq!(None, c.step(WEEK + 4, 20, false, WEEK, 20));
+        assert_eq!(WEEK + 4, c.last);
+        assert_eq!(None, c.step(WEEK + 5, 20, false, WEEK, 20));
+    }
+
+    #[test]
+    fn charging_that_never_gets_full_is_given_up() {
+        let mut c = Calibration {
+            last: 0,
+            phase: None,
+            started: None,
+        };
+        let auto = Some(ChargeBehaviour::Auto);
+
+        assert_eq!(auto, c.step(WEEK, 80, false, WEEK, 20));
+        let end = WEEK + CHARGE_TIMEOUT;
+        assert_eq!(auto, c.step(end - 1, 99, false, WEEK, 20));
+        assert_eq!(None, c.step(end, 99, false, WEEK, 20));
+        assert_eq!((None, end), (c.phase, c.last));
+        assert_eq!(None, c.step(end + WEEK - 1, 99, false, WEEK, 20));
+        assert_eq!(auto, c.step(end + WEEK, 99, false, WEEK, 20));
+
+        // A postponed cycle gets the full deadline once it continues.
+        assert!(c.postpone());
+        assert!(!c.postpone());
+        assert_eq!(auto, c.step(end * 2, 99, false, WEEK, 20));
+        assert_eq!(Some(end * 2), c.started);
+    }
+
+    #[test]
+    fn state_persists() {
+        let dir = TestDir::new("calibration");
+        let mut c = Calibration::load(&dir, 1000).unwrap();
+        assert_eq!(1000, c.last);
+        c.phase = Some(Phase::Charging);
+        c.save(&dir).unwrap();
+        assert_eq!(c, Calibration::load(&dir, 2000).unwrap());
+    }
+}
@@ -0,0 +1,27 @@
+use crate::sysfs;
+
+/// The AC adapter's negotiated power in watts, from its maximum voltage (µV)
+/// and current (µA). `None` if the adapter doesn't report them.
+pub fn watts() -> Option<f64> {
+    let read = |attr| {
+        sysfs::read(sysfs::ac(attr))
+            .ok()
+            .and_then(|s| s.trim().parse::<i64>().ok())
+    };
+    Some(to_watts(read("voltage_max")?, read("current_max")?))
+}
+
+fn to_watts(voltage_uv: i64, current_ua: i64) -> f64 {
+    voltage_uv as f64 / 1_000_000.0 * current_ua as f64 / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::charger::to_watts;
+
+    #[test]
+    fn negotiated_power() {
+        assert_eq!(30.0, to_watts(15_000_000, 2_000_000));
+        assert_eq!(96.0, to_watts(20_000_000, 4_800_000));
+    }
+}
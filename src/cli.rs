@@ -0,0 +1,435 @@
+use std::path::PathBuf;
+
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+
+use crate::config::{self, Config};
+use crate::full;
+
+/// What to do after parsing the command line.
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    /// Run the daemon.
+    Run,
+    /// Ask the running daemon to drain to this level.
+    Drain(i8),
+    /// Turn travel mode on or off.
+    Travel(bool),
+    /// Turn storage mode on or off.
+    Storage(bool),
+    /// Ask the running daemon to charge to full once, within this many hours.
+    FullCharge(u64),
+    /// Ask the running daemon to use these thresholds, or the configured ones again.
+    SetThresholds(Option<(i8, i8)>),
+    /// Switch to a named profile from the config, or back to the top-level thresholds.
+    Profile(Option<String>),
+    /// Print what the running daemon last saw and did, again whenever it
+    /// changes if following.
+    Status { format: Format, follow: bool },
+    /// Make sure the battery is charged enough for an update, waiting up to
+    /// this many minutes.
+    PreUpdate(u64),
+    /// Set the configured behaviour for sleep, from the systemd-sleep hook.
+    PrepareSleep,
+    /// Replay a recorded trace through the policy.
+    Simulate(PathBuf),
+}
+
+/// How `status` prints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    /// One line of JSON for a Waybar custom module.
+    Waybar,
+}
+
+/// Command line arguments. Overrides take precedence over the config file.
+#[derive(Debug)]
+pub struct Cli {
+    pub config: PathBuf,
+    pub low: Option<i8>,
+    pub high: Option<i8>,
+    pub interval: Option<u64>,
+    pub device: Option<PathBuf>,
+    /// Prefix for all power_supply paths.
+    pub sysfs_root: Option<PathBuf>,
+    pub record_trace: Option<PathBuf>,
+    /// Refuse to start instead of using defaults when the config file is missing.
+    pub require_config: bool,
+    pub action: Action,
+}
+
+fn command() -> Command {
+    let percent = || value_parser!(i8).range(0..=100);
+    Command::new("macsmc-charged")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Keeps the battery of Apple silicon Macs between two charge thresholds")
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .env("MACSMC_CONFIG")
+                .default_value(config::DEFAULT_PATH)
+                .value_parser(value_parser!(PathBuf))
+                .help("Config file to load"),
+        )
+        .arg(
+            Arg::new("require-config")
+                .long("require-config")
+                .env("MACSMC_REQUIRE_CONFIG")
+                .action(ArgAction::SetTrue)
+                .help("Refuse to start if the config file is missing, instead of using defaults"),
+        )
+        .arg(
+            Arg::new("low")
+                .long("low")
+                .value_name("PERCENT")
+                .value_parser(percent())
+                .help("Charge back up when capacity drops below this"),
+        )
+        .arg(
+            Arg::new("high")
+                .long("high")
+                .value_name("PERCENT")
+                .value_parser(percent())
+                .help("Never charge past this, and discharge down to it when above"),
+        )
+        .arg(
+            Arg::new("interval")
+                .long("interval")
+                .value_name("SECONDS")
+                .value_parser(value_parser!(u64).range(1..))
+                .help("Seconds between evaluations"),
+        )
+        .arg(
+            Arg::new("device")
+                .long("device")
+                .value_name("PATH")
+                .value_parser(value_parser!(PathBuf))
+                .help("The battery's power_supply directory"),
+        )
+        .arg(
+            Arg::new("sysfs-root")
+                .long("sysfs-root")
+                .value_name("PATH")
+                .env("MACSMC_SYSFS_ROOT")
+                .value_parser(value_parser!(PathBuf))
+                .help("Prefix all power_supply paths, e.g. where the host's /sys is mounted in a container"),
+        )
+        .arg(
+            Arg::new("record-trace")
+                .long("record-trace")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf))
+                .help("Append every raw sysfs read and write to FILE, for bug reports"),
+        )
+        .subcommand(
+            Command::new("drain")
+                .about("Ask the running daemon to discharge to a level, then resume normal limits")
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .value_name("PERCENT")
+                        .required(true)
+                        .value_parser(percent()),
+                ),
+        )
+        .subcommand(
+            Command::new("travel")
+                .about("Allow a full charge until the battery has been used")
+                .arg(Arg::new("state").required(true).value_parser(["on", "off"])),
+        )
+        .subcommand(
+            Command::new("storage")
+                .about("Hold the battery at storage_level (50% by default) for long-term storage")
+                .arg(Arg::new("state").required(true).value_parser(["on", "off"])),
+        )
+        .subcommand(
+            Command::new("full-charge")
+                .about("Charge to full once, then resume normal limits")
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .value_name("HOURS")
+                        .default_value("8")
+                        .value_parser(value_parser!(u64).range(1..=full::MAX_TIMEOUT_HOURS))
+                        .help("Give up if the battery isn't full by then"),
+                ),
+        )
+        .subcommand(
+            Command::new("set-thresholds")
+                .about("Have the running daemon use other thresholds until reset")
+                .arg(
+                    Arg::new("low")
+                        .value_name("LOW")
+                        .required_unless_present("reset")
+                        .value_parser(percent()),
+                )
+                .arg(
+                    Arg::new("high")
+                        .value_name("HIGH")
+                        .required_unless_present("reset")
+                        .value_parser(percent()),
+                )
+                .arg(
+                    Arg::new("reset")
+                        .long("reset")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["low", "high"])
+                        .help("Go back to the configured thresholds"),
+                ),
+        )
+        .subcommand(
+            Command::new("profile")
+                .about("Have the running daemon use a profile from the config, until reset")
+                .arg(
+                    Arg::new("name")
+                        .value_name("NAME")
+                        .required_unless_present("reset"),
+                )
+                .arg(
+                    Arg::new("reset")
+                        .long("reset")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("name")
+                        .help("Go back to the top-level thresholds"),
+                ),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Print what the running daemon last saw and did")
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .default_value("text")
+                        .value_parser(["text", "json", "waybar"]),
+                )
+                .arg(
+                    Arg::new("follow")
+                        .long("follow")
+                        .action(ArgAction::SetTrue)
+                        .help("Keep running and print again whenever the status changes"),
+                ),
+        )
+        .subcommand(
+            Command::new("pre-update")
+                .about("Exit 0 once updates are pending and the battery is charged enough to install them")
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .value_name("MINUTES")
+                        .default_value("60")
+                        .value_parser(value_parser!(u64).range(..=full::MAX_TIMEOUT_HOURS * 60))
+                        .help("Fail if the battery isn't charged enough by then"),
+                ),
+        )
+        .subcommand(
+            Command::new("prepare-sleep")
+                .about("Set sleep_behaviour from the config before suspending, for the systemd-sleep hook"),
+        )
+        .subcommand(
+            Command::new("simulate")
+                .about("Replay a trace from --record-trace and print the behaviour changes made")
+                .arg(
+                    Arg::new("trace")
+                        .value_name("FILE")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
+                ),
+        )
+}
+
+impl Cli {
+    pub fn parse() -> Self {
+        Self::from_matches(&command().get_matches())
+    }
+
+    fn from_matches(m: &ArgMatches) -> Self {
+        let action = match m.subcommand() {
+            Some(("drain", sub)) => Action::Drain(*sub.get_one::<i8>("to").unwrap()),
+            Some(("travel", sub)) => {
+                Action::Travel(sub.get_one::<String>("state").is_some_and(|s| s == "on"))
+            }
+            Some(("storage", sub)) => {
+                Action::Storage(sub.get_one::<String>("state").is_some_and(|s| s == "on"))
+            }
+            Some(("full-charge", sub)) => {
+                Action::FullCharge(*sub.get_one::<u64>("timeout").unwrap())
+            }
+            Some(("set-thresholds", sub)) => Action::SetThresholds(
+                sub.get_one::<i8>("low")
+                    .zip(sub.get_one::<i8>("high"))
+                    .map(|(l, h)| (*l, *h)),
+            ),
+            Some(("profile", sub)) => Action::Profile(sub.get_one::<String>("name").cloned()),
+            Some(("pre-update", sub)) => Action::PreUpdate(*sub.get_one::<u64>("timeout").unwrap()),
+            Some(("prepare-sleep", _)) => Action::PrepareSleep,
+            Some(("status", sub)) => Action::Status {
+                format: match sub.get_one::<String>("format").map(String::as_str) {
+                    Some("json") => Format::Json,
+                    Some("waybar") => Format::Waybar,
+                    _ => Format::Text,
+                },
+                follow: sub.get_flag("follow"),
+            },
+            Some(("simulate", sub)) => {
+                Action::Simulate(sub.get_one::<PathBuf>("trace").unwrap().clone())
+            }
+            _ => Action::Run,
+        };
+        Self {
+            config: m.get_one::<PathBuf>("config").unwrap().clone(),
+            low: m.get_one("low").copied(),
+            high: m.get_one("high").copied(),
+            interval: m.get_one("interval").copied(),
+            device: m.get_one("device").cloned(),
+            sysfs_root: m.get_one("sysfs-root").cloned(),
+            record_trace: m.get_one("record-trace").cloned(),
+            require_config: m.get_flag("require-config"),
+            action,
+        }
+    }
+
+    /// Apply the overrides to `config`, checking the result is still valid.
+    pub fn apply(&self, config: &mut Config) -> Result<(), anyhow::Error> {
+        config.apply_env(|k| std::env::var(k).ok())?;
+        if let Some(low) = self.low {
+            config.low_threshold = low;
+        }
+        if let Some(high) = self.high {
+            config.high_threshold = high;
+        }
+        if let Some(interval) = self.interval {
+            config.interval = interval;
+        }
+        if let Some(device) = &self.device {
+            config.battery = device.clone();
+        }
+        config.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::cli::{command, Action, Cli, Format};
+    use crate::config::Config;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        command()
+            .try_get_matches_from(std::iter::once("macsmc-charged").chain(args.iter().copied()))
+            .map(|m| Cli::from_matches(&m))
+    }
+
+    #[test]
+    fn overrides_apply_to_config() {
+        let cli = parse(&[
+            "--low",
+            "60",
+            "--high",
+            "75",
+            "--interval",
+            "30",
+            "--device",
+            "/sys/class/power_supply/battery",
+            "--sysfs-root",
+            "/host",
+        ])
+        .unwrap();
+        assert_eq!(Action::Run, cli.action);
+        assert_eq!(Some(PathBuf::from("/host")), cli.sysfs_root);
+        assert!(!cli.require_config);
+        assert!(parse(&["--require-config"]).unwrap().require_config);
+        let mut c = Config::default();
+        cli.apply(&mut c).unwrap();
+        assert_eq!(60, c.low_threshold);
+        assert_eq!(75, c.high_threshold);
+        assert_eq!(30, c.interval);
+        assert_eq!(PathBuf::from("/sys/class/power_supply/battery"), c.battery);
+
+        let mut c = Config::default();
+        assert!(parse(&["--low", "85"]).unwrap().apply(&mut c).is_err());
+        assert!(parse(&["--high", "101"]).is_err());
+        assert!(parse(&["--interval", "0"]).is_err());
+    }
+
+    #[test]
+    fn parse_subcommands() {
+        assert_eq!(
+            Action::Drain(50),
+            parse(&["drain", "--to", "50"]).unwrap().action
+        );
+        assert_eq!(
+            Action::Travel(true),
+            parse(&["travel", "on"]).unwrap().action
+        );
+        assert_eq!(
+            Action::Travel(false),
+            parse(&["travel", "off"]).unwrap().action
+        );
+        assert!(parse(&["travel", "maybe"]).is_err());
+        assert_eq!(
+            Action::Storage(true),
+            parse(&["storage", "on"]).unwrap().action
+        );
+        assert!(parse(&["drain"]).is_err());
+        assert_eq!(
+            Action::SetThresholds(Some((60, 80))),
+            parse(&["set-thresholds", "60", "80"]).unwrap().action
+        );
+        assert_eq!(
+            Action::SetThresholds(None),
+            parse(&["set-thresholds", "--reset"]).unwrap().action
+        );
+        assert!(parse(&["set-thresholds", "60"]).is_err());
+        assert_eq!(
+            Action::FullCharge(8),
+            parse(&["full-charge"]).unwrap().action
+        );
+        assert_eq!(
+            Action::FullCharge(2),
+            parse(&["full-charge", "--timeout", "2"]).unwrap().action
+        );
+        assert!(parse(&["full-charge", "--timeout", "18446744073709551615"]).is_err());
+        assert!(parse(&["set-thresholds", "60", "80", "--reset"]).is_err());
+        assert_eq!(
+            Action::Profile(Some("desk".to_string())),
+            parse(&["profile", "desk"]).unwrap().action
+        );
+        assert_eq!(
+            Action::Profile(None),
+            parse(&["profile", "--reset"]).unwrap().action
+        );
+        assert!(parse(&["profile"]).is_err());
+        assert_eq!(
+            Action::PreUpdate(60),
+            parse(&["pre-update"]).unwrap().action
+        );
+        assert!(parse(&["pre-update", "--timeout", "18446744073709551615"]).is_err());
+        assert_eq!(
+            Action::Status {
+                format: Format::Text,
+                follow: false
+            },
+            parse(&["status"]).unwrap().action
+        );
+        assert_eq!(
+            Action::Status {
+                format: Format::Json,
+                follow: false
+            },
+            parse(&["status", "--format", "json"]).unwrap().action
+        );
+        assert_eq!(
+            Action::Status {
+                format: Format::Waybar,
+                follow: true
+            },
+            parse(&["status", "--format", "waybar", "--follow"])
+                .unwrap()
+                .action
+        );
+        assert!(parse(&["status", "--format", "xml"]).is_err());
+    }
+}
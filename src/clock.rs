@@ -0,0 +1,99 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Seconds local time is ahead of UTC at `secs`, 0 if unknown.
+pub fn utc_offset(secs: u64) -> i64 {
+    let t = secs as libc::time_t;
+    // SAFETY: tm is plain data, all zeroes is a valid value.
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    // SAFETY: both pointers are valid for the duration of the call.
+    if unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff
+}
+
+/// Days since the unix epoch, in UTC.
+pub fn today() -> i64 {
+    (now() / 86400) as i64
+}
+
+/// Format days since the unix epoch as YYYY-MM-DD.
+pub fn format_day(days: i64) -> String {
+    // Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// Parse a YYYY-MM-DD date into days since the unix epoch.
+pub fn parse_day(s: &str) -> Option<i64> {
+    let mut parts = s.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    // Howard Hinnant's days_from_civil
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if m > 2 { m - 3 } else { m + 9 };
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
+
+/// Format seconds since the unix epoch as an RFC 3339 UTC timestamp.
+pub fn format_timestamp(secs: u64) -> String {
+    let (days, rem) = (secs / 86400, secs % 86400);
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        format_day(days as i64),
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{format_day, format_timestamp, parse_day};
+
+    #[test]
+    fn format_days_since_epoch() {
+        assert_eq!("1970-01-01", format_day(0));
+        assert_eq!("2000-02-29", format_day(11016));
+        assert_eq!("2023-04-01", format_day(19448));
+    }
+
+    #[test]
+    fn parse_days() {
+        assert_eq!(Some(0), parse_day("1970-01-01"));
+        assert_eq!(Some(11016), parse_day("2000-02-29"));
+        assert_eq!(Some(19448), parse_day("2023-04-01"));
+        assert_eq!(None, parse_day("2023-13-01"));
+        assert_eq!(None, parse_day("yesterday"));
+    }
+
+    #[test]
+    fn format_timestamps() {
+        assert_eq!("1970-01-01T00:00:00Z", format_timestamp(0));
+        assert_eq!(
+            "2023-04-01T13:05:09Z",
+            format_timestamp(19448 * 86400 + 47109)
+        );
+    }
+}
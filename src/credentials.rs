@@ -0,0 +1,53 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// Read a secret from `path`, without the trailing newline most editors add.
+pub fn read(path: &Path) -> Result<String, anyhow::Error> {
+    let s =
+        fs::read_to_string(path).with_context(|| format!("Could not read {}", path.display()))?;
+    Ok(s.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// The systemd credential `name`, passed with `LoadCredential=` or
+/// `SetCredential=`. `None` if the service wasn't given one.
+pub fn load(name: &str) -> Result<Option<String>, anyhow::Error> {
+    load_from(
+        std::env::var_os("CREDENTIALS_DIRECTORY").map(PathBuf::from),
+        name,
+    )
+}
+
+fn load_from(dir: Option<PathBuf>, name: &str) -> Result<Option<String>, anyhow::Error> {
+    let Some(dir) = dir else {
+        return Ok(None);
+    };
+    let path = dir.join(name);
+    match fs::metadata(&path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        _ => read(&path).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::credentials::load_from;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn loads_credentials() {
+        let dir = TestDir::new("credentials");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("mqtt-password"), "secret\n").unwrap();
+        assert_eq!(
+            Some("secret".to_string()),
+            load_from(Some(dir.to_path_buf()), "mqtt-password").unwrap()
+        );
+        assert_eq!(None, load_from(Some(dir.to_path_buf()), "other").unwrap());
+        assert_eq!(None, load_from(None, "mqtt-password").unwrap());
+    }
+}
@@ -0,0 +1,123 @@
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::ChargeBehaviour;
+
+/// Notices when force-discharge is in effect but capacity isn't going down,
+/// e.g. because the firmware ignores it, so it can be given up on.
+#[derive(Debug)]
+pub struct DischargeWatch {
+    /// How long capacity may stay put before force-discharge is given up on.
+    timeout: Duration,
+    /// Lowest capacity seen while discharging, and when it was first seen.
+    since: Option<(i8, Instant)>,
+    ac_online: Option<bool>,
+    stuck: bool,
+}
+
+impl DischargeWatch {
+    /// A zero `timeout` never gives up.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            since: None,
+            ac_online: None,
+            stuck: false,
+        }
+    }
+
+    /// Returns true if force-discharge should not be used, until the policy
+    /// stops asking for it or the AC state changes.
+    pub fn observe(
+        &mut self,
+        now: Instant,
+        capacity: i8,
+        behaviour: ChargeBehaviour,
+        wanted: ChargeBehaviour,
+        ac_online: Option<bool>,
+    ) -> bool {
+        if wanted != ChargeBehaviour::ForceDischarge || ac_online != self.ac_online {
+            if self.stuck {
+                info!("Force-discharge may be used again");
+            }
+            self.since = None;
+            self.stuck = false;
+            self.ac_online = ac_online;
+        }
+        if self.stuck || self.timeout.is_zero() || wanted != ChargeBehaviour::ForceDischarge {
+            return self.stuck;
+        }
+        if behaviour != ChargeBehaviour::ForceDischarge {
+            return false;
+        }
+        match self.since {
+            Some((c, _)) if capacity < c => self.since = Some((capacity, now)),
+            Some((c, t)) if now.duration_since(t) >= self.timeout => {
+                warn!(
+                    "Force-discharge has been on for {} minutes but the battery is still at {c}%. \
+                     Inhibiting charging instead.",
+                    self.timeout.as_secs() / 60
+                );
+                self.stuck = true;
+            }
+            Some(_) => {}
+            None => self.since = Some((capacity, now)),
+        }
+        self.stuck
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::discharge::DischargeWatch;
+    use crate::ChargeBehaviour;
+
+    const TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+    #[test]
+    fn gives_up_when_capacity_does_not_drop() {
+        let mut w = DischargeWatch::new(TIMEOUT);
+        let t = Instant::now();
+        let fd = ChargeBehaviour::ForceDischarge;
+
+        assert!(!w.observe(t, 85, ChargeBehaviour::Auto, fd, Some(true)));
+        assert!(!w.observe(t, 85, fd, fd, Some(true)));
+        assert!(!w.observe(t + TIMEOUT / 2, 85, fd, fd, Some(true)));
+        assert!(w.observe(t + TIMEOUT, 85, fd, fd, Some(true)));
+        assert!(w.observe(
+            t + TIMEOUT * 2,
+            85,
+            ChargeBehaviour::InhibitCharge,
+            fd,
+            Some(true)
+        ));
+
+        // Retried once the AC state changes.
+        assert!(!w.observe(
+            t + TIMEOUT * 3,
+            85,
+            ChargeBehaviour::InhibitCharge,
+            fd,
+            None
+        ));
+    }
+
+    #[test]
+    fn progress_keeps_discharging() {
+        let mut w = DischargeWatch::new(TIMEOUT);
+        let t = Instant::now();
+        let fd = ChargeBehaviour::ForceDischarge;
+        let minute = Duration::from_secs(60);
+
+        assert!(!w.observe(t, 85, fd, fd, Some(true)));
+        assert!(!w.observe(t + TIMEOUT - minute, 84, fd, fd, Some(true)));
+        assert!(!w.observe(t + TIMEOUT, 84, fd, fd, Some(true)));
+
+        let mut w = DischargeWatch::new(Duration::ZERO);
+        assert!(!w.observe(t, 85, fd, fd, Some(true)));
+        assert!(!w.observe(t + TIMEOUT * 10, 85, fd, fd, Some(true)));
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::state::RequestFile;
+
+const FILE: RequestFile = RequestFile::new("drain");
+
+/// Ask the running daemon to force-discharge down to `target` percent.
+pub fn request(dir: &Path, target: i8) -> Result<(), anyhow::Error> {
+    if !(0..=100).contains(&target) {
+        return Err(anyhow!(
+            "Drain target must be between 0 and 100, got {target}"
+        ));
+    }
+    FILE.write(dir, &target.to_string())
+}
+
+/// The currently requested drain target, if any.
+pub fn pending(dir: &Path) -> Result<Option<i8>, anyhow::Error> {
+    Ok(FILE.read(dir)?.map(|s| s.parse()).transpose()?)
+}
+
+pub fn clear(dir: &Path) -> Result<(), anyhow::Error> {
+    FILE.clear(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::drain::request;
+
+    #[test]
+    fn reject_invalid_target() {
+        assert!(request(Path::new("/nonexistent"), 101).is_err());
+        assert!(request(Path::new("/nonexistent"), -1).is_err());
+    }
+}
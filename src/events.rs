@@ -0,0 +1,108 @@
+use crate::reason::{Override, Reason};
+use crate::ChargeBehaviour;
+
+/// Things that happen in the control loop, published to every subscriber.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A new battery reading. AC, energy and full charge capacity are `None`
+    /// if they couldn't be read.
+    CapacityRead {
+        capacity: i8,
+        behaviour: ChargeBehaviour,
+        ac_online: Option<bool>,
+        energy: Option<i64>,
+        /// Full charge capacity and its design value.
+        charge_full: Option<(i64, i64)>,
+    },
+    AcChanged {
+        online: bool,
+    },
+    /// A new charge behaviour was written to sysfs.
+    TransitionApplied {
+        old: ChargeBehaviour,
+        new: ChargeBehaviour,
+        capacity: i8,
+        reason: Reason,
+    },
+    /// A new charge behaviour would have been written, but monitor mode is on.
+    TransitionRecommended {
+        old: ChargeBehaviour,
+        new: ChargeBehaviour,
+        capacity: i8,
+        reason: Reason,
+    },
+    /// An override of the normal policy was started (`Some`) or ended (`None`).
+    OverrideSet {
+        behaviour: Option<ChargeBehaviour>,
+        reason: Override,
+    },
+    /// The thresholds changed after the config was reloaded.
+    ThresholdsChanged {
+        low: i8,
+        high: i8,
+    },
+    /// A profile was selected (`Some`), or the top-level thresholds are
+    /// used again (`None`).
+    ProfileChanged {
+        name: Option<String>,
+    },
+    /// A non-fatal error.
+    Error(String),
+}
+
+pub trait Subscriber {
+    fn handle(&mut self, event: &Event);
+}
+
+/// Synchronous fan-out of events to subscribers, in subscription order.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Box<dyn Subscriber>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&mut self, subscriber: Box<dyn Subscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    pub fn publish(&mut self, event: Event) {
+        for s in self.subscribers.iter_mut() {
+            s.handle(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::events::{Event, EventBus, Subscriber};
+
+    struct Collector(Rc<RefCell<Vec<Event>>>);
+
+    impl Subscriber for Collector {
+        fn handle(&mut self, event: &Event) {
+            self.0.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn events_reach_every_subscriber() {
+        let a = Rc::new(RefCell::new(Vec::new()));
+        let b = Rc::new(RefCell::new(Vec::new()));
+        let mut bus = EventBus::default();
+        bus.subscribe(Box::new(Collector(a.clone())));
+        bus.subscribe(Box::new(Collector(b.clone())));
+
+        bus.publish(Event::AcChanged { online: true });
+        bus.publish(Event::Error("oops".to_string()));
+
+        let expected = vec![
+            Event::AcChanged { online: true },
+            Event::Error("oops".to_string()),
+        ];
+        assert_eq!(expected, *a.borrow());
+        assert_eq!(expected, *b.borrow());
+    }
+}
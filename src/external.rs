@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::ChargeBehaviour;
+
+/// Notices charge behaviour changes the daemon didn't make, e.g. someone
+/// writing `auto` by hand to top up, and honours them for a grace period.
+#[derive(Debug)]
+pub struct ExternalChange {
+    grace: Duration,
+    /// What the daemon left the behaviour at last time.
+    last: Option<ChargeBehaviour>,
+    /// The external behaviour and when its grace period ends.
+    honoured: Option<(ChargeBehaviour, Instant)>,
+}
+
+impl ExternalChange {
+    /// A zero `grace` never honours external changes.
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            grace,
+            last: None,
+            honoured: None,
+        }
+    }
+
+    /// Returns the behaviour to keep instead of the policy's, if any.
+    /// `trusted` is false when the behaviour may have changed for other
+    /// reasons, such as the AC state changing or the device being reset.
+    pub fn observe(
+        &mut self,
+        now: Instant,
+        read: ChargeBehaviour,
+        trusted: bool,
+    ) -> Option<ChargeBehaviour> {
+        let changed = self.last.is_some_and(|l| l != read);
+        if !self.grace.is_zero() && trusted && changed {
+            info!(
+                "Charge behaviour was changed to {read} outside the daemon, leaving it for {} minutes",
+                self.grace.as_secs() / 60
+            );
+            self.honoured = Some((read, now + self.grace));
+        }
+        match self.honoured {
+            Some((_, until)) if now >= until => {
+                info!("Grace period for the external charge behaviour change is over, resuming control");
+                self.honoured = None;
+                None
+            }
+            Some(_) => {
+                // Keep whatever is there, even if changed by hand again.
+                self.honoured = self.honoured.map(|(_, until)| (read, until));
+                Some(read)
+            }
+            None => None,
+        }
+    }
+
+    /// Record the behaviour the daemon left in place this evaluation.
+    pub fn left_at(&mut self, b: ChargeBehaviour) {
+        self.last = Some(b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::external::ExternalChange;
+    use crate::ChargeBehaviour;
+
+    #[test]
+    fn honours_external_change_for_grace_period() {
+        let grace = Duration::from_secs(1800);
+        let mut e = ExternalChange::new(grace);
+        let t = Instant::now();
+        let (auto, inhibit) = (ChargeBehaviour::Auto, ChargeBehaviour::InhibitCharge);
+
+        assert_eq!(None, e.observe(t, inhibit, true));
+        e.left_at(inhibit);
+        assert_eq!(Some(auto), e.observe(t, auto, true));
+        e.left_at(auto);
+        assert_eq!(Some(auto), e.observe(t + grace / 2, auto, true));
+        e.left_at(auto);
+        assert_eq!(None, e.observe(t + grace, auto, true));
+    }
+
+    #[test]
+    fn untrusted_changes_and_zero_grace_are_ignored() {
+        let (auto, inhibit) = (ChargeBehaviour::Auto, ChargeBehaviour::InhibitCharge);
+        let t = Instant::now();
+
+        let mut e = ExternalChange::new(Duration::from_secs(1800));
+        e.left_at(inhibit);
+        assert_eq!(None, e.observe(t, auto, false));
+
+        let mut e = ExternalChange::new(Duration::ZERO);
+        e.left_at(inhibit);
+        assert_eq!(None, e.observe(t, auto, true));
+    }
+}
@@ -0,0 +1,199 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::events::{Event, Subscriber};
+use crate::reason::Override;
+use crate::ChargeBehaviour;
+
+/// How long charging may make no progress before a firmware limit is assumed.
+const STALL_TIME: Duration = Duration::from_secs(30 * 60);
+
+/// Detects when charging is allowed but capacity never rises, which means
+/// the firmware is enforcing a limit of its own.
+pub struct FirmwareLimitDetector {
+    /// Capacity charging is expected to reach without an override.
+    high: i8,
+    /// Overrides charging the battery, which then should reach 100%.
+    charging: Vec<Override>,
+    /// Capacity and when charging started stalling at it.
+    stalled: Option<(i8, Instant)>,
+    reported: bool,
+    /// Capacity the firmware is limiting at, shared with the status.
+    limit: Rc<Cell<Option<i8>>>,
+}
+
+impl FirmwareLimitDetector {
+    pub fn new(high: i8) -> Self {
+        Self {
+            high,
+            charging: Vec::new(),
+            stalled: None,
+            reported: false,
+            limit: Rc::default(),
+        }
+    }
+
+    /// The capacity a firmware limit appears to hold the battery at, kept
+    /// up to date once the detector is subscribed.
+    pub fn limit(&self) -> Rc<Cell<Option<i8>>> {
+        self.limit.clone()
+    }
+
+    /// Capacity charging should reach now.
+    fn target(&self) -> i8 {
+        if self.charging.is_empty() {
+            self.high
+        } else {
+            100
+        }
+    }
+
+    /// Returns the capacity the firmware appears to be limiting at, once.
+    fn observe(
+        &mut self,
+        now: Instant,
+        capacity: i8,
+        behaviour: ChargeBehaviour,
+        ac_online: Option<bool>,
+    ) -> Option<i8> {
+        let charging_wanted = behaviour == ChargeBehaviour::Auto
+            && ac_online == Some(true)
+            && capacity < self.target();
+        match self.stalled {
+            Some((c, since)) if charging_wanted && capacity <= c => {
+                if !self.reported && now.duration_since(since) >= STALL_TIME {
+                    self.reported = true;
+                    self.limit.set(Some(c));
+                    return Some(c);
+                }
+            }
+            _ if charging_wanted => {
+                if self.reported {
+                    info!("Battery is charging again, firmware limit no longer appears active");
+                }
+                self.stalled = Some((capacity, now));
+                self.reported = false;
+                self.limit.set(None);
+            }
+            _ => {
+                self.stalled = None;
+                self.reported = false;
+                self.limit.set(None);
+            }
+        }
+        None
+    }
+}
+
+impl Subscriber for FirmwareLimitDetector {
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::CapacityRead {
+                capacity,
+                behaviour,
+                ac_online,
+                ..
+            } => {
+                if let Some(c) = self.observe(Instant::now(), *capacity, *behaviour, *ac_online) {
+                    warn!(
+                        "Charging is allowed but the battery has stayed at {c}% for {} minutes. \
+                         A firmware charge limit appears to be active.",
+                        STALL_TIME.as_secs() / 60
+                    );
+                }
+            }
+            Event::ThresholdsChanged { high, .. } => self.high = *high,
+            Event::OverrideSet { behaviour, reason } => {
+                self.charging.retain(|r| r != reason);
+                if *behaviour == Some(ChargeBehaviour::Auto) {
+                    self.charging.push(*reason);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::events::{Event, Subscriber};
+    use crate::firmware::{FirmwareLimitDetector, STALL_TIME};
+    use crate::reason::Override;
+    use crate::{ChargeBehaviour, HIGH_THRESHOLD};
+
+    #[test]
+    fn stalled_charging_is_reported_once() {
+        let mut d = FirmwareLimitDetector::new(HIGH_THRESHOLD);
+        let t = Instant::now();
+        let auto = ChargeBehaviour::Auto;
+
+        assert_eq!(None, d.observe(t, 75, auto, Some(true)));
+        assert_eq!(None, d.observe(t + STALL_TIME / 2, 75, auto, Some(true)));
+        assert_eq!(Some(75), d.observe(t + STALL_TIME, 75, auto, Some(true)));
+        assert_eq!(None, d.observe(t + STALL_TIME * 2, 75, auto, Some(true)));
+
+        let limit = d.limit();
+        assert_eq!(Some(75), limit.get());
+        d.observe(t + STALL_TIME * 2, 76, auto, Some(true));
+        assert_eq!(None, limit.get());
+    }
+
+    #[test]
+    fn progress_or_other_states_reset_detection() {
+        let mut d = FirmwareLimitDetector::new(HIGH_THRESHOLD);
+        let t = Instant::now();
+        let auto = ChargeBehaviour::Auto;
+        let minute = Duration::from_secs(60);
+
+        assert_eq!(None, d.observe(t, 75, auto, Some(true)));
+        assert_eq!(
+            None,
+            d.observe(t + STALL_TIME - minute, 76, auto, Some(true))
+        );
+        assert_eq!(None, d.observe(t + STALL_TIME, 76, auto, Some(true)));
+
+        assert_eq!(None, d.observe(t, 75, auto, Some(false)));
+        assert_eq!(None, d.observe(t + STALL_TIME, 75, auto, Some(false)));
+
+        let inhibit = ChargeBehaviour::InhibitCharge;
+        assert_eq!(None, d.observe(t, 75, inhibit, Some(true)));
+        assert_eq!(None, d.observe(t + STALL_TIME, 75, inhibit, Some(true)));
+    }
+
+    #[test]
+    fn follows_reloaded_high_threshold() {
+        let mut d = FirmwareLimitDetector::new(HIGH_THRESHOLD);
+        d.handle(&Event::ThresholdsChanged { low: 60, high: 75 });
+        let t = Instant::now();
+        let auto = ChargeBehaviour::Auto;
+
+        assert_eq!(None, d.observe(t, 75, auto, Some(true)));
+        assert_eq!(None, d.observe(t + STALL_TIME, 75, auto, Some(true)));
+    }
+
+    #[test]
+    fn charging_overrides_expect_a_full_battery() {
+        let mut d = FirmwareLimitDetector::new(HIGH_THRESHOLD);
+        let t = Instant::now();
+        let auto = ChargeBehaviour::Auto;
+        d.handle(&Event::OverrideSet {
+            behaviour: Some(auto),
+            reason: Override::FullCharge,
+        });
+
+        assert_eq!(None, d.observe(t, 80, auto, Some(true)));
+        assert_eq!(Some(80), d.observe(t + STALL_TIME, 80, auto, Some(true)));
+
+        d.handle(&Event::OverrideSet {
+            behaviour: None,
+            reason: Override::FullCharge,
+        });
+        assert_eq!(None, d.observe(t + STALL_TIME * 2, 80, auto, Some(true)));
+        assert_eq!(None, d.limit().get());
+    }
+}
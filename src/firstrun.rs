@@ -0,0 +1,108 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::{clock, sysfs};
+
+/// Whether there is neither a config nor any state, so the daemon has
+/// never run here before.
+pub fn is_first_run(config: &Path, state: &Path) -> bool {
+    !config.exists() && fs::read_dir(state).map_or(true, |mut d| d.next().is_none())
+}
+
+/// The battery's full charge capacity and its design value, in µAh.
+pub fn battery_size() -> Option<(i64, i64)> {
+    let read = |attr| {
+        sysfs::read(sysfs::battery(attr))
+            .ok()
+            .and_then(|s| s.trim().parse::<i64>().ok())
+    };
+    read("charge_full")
+        .zip(read("charge_full_design"))
+        .filter(|&(_, design)| design > 0)
+}
+
+/// Thresholds for a battery at `health` percent of its design capacity. A
+/// worn battery is allowed to charge further, so it still lasts a while
+/// off the charger.
+pub fn suggest(health: Option<f64>) -> (i8, i8) {
+    match health {
+        Some(h) if h < 80.0 => (75, 90),
+        Some(h) if h < 90.0 => (70, 85),
+        _ => (70, 80),
+    }
+}
+
+/// A commented config with suggested thresholds for a battery of `size`.
+pub fn starter_config(size: Option<(i64, i64)>) -> String {
+    let health = size.map(|(full, design)| full as f64 * 100.0 / design as f64);
+    let (low, high) = suggest(health);
+    let battery = match size {
+        Some((full, design)) => format!(
+            "# The battery holds {} of its {} mAh design capacity ({:.0}% health).\n",
+            full / 1000,
+            design / 1000,
+            health.unwrap_or_default()
+        ),
+        None => "# The battery's health could not be read.\n".to_string(),
+    };
+    format!(
+        "# Starter config generated by macsmc-charged on first run, {}.\n\
+         {battery}\
+         # See config.example.toml for all settings.\n\
+         \n\
+         # Charge back up to high_threshold once capacity drops below this.\n\
+         low_threshold = {low}\n\
+         # Never charge past this, and discharge down to it when above.\n\
+         high_threshold = {high}\n",
+        clock::format_day(clock::today())
+    )
+}
+
+/// Write `contents` to `path`, never replacing an existing file.
+pub fn write(path: &Path, contents: &str) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?
+        .write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::config::Config;
+    use crate::firstrun::{is_first_run, starter_config, suggest, write};
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn worn_batteries_charge_further() {
+        assert_eq!((70, 80), suggest(None));
+        assert_eq!((70, 80), suggest(Some(95.0)));
+        assert_eq!((70, 85), suggest(Some(85.0)));
+        assert_eq!((75, 90), suggest(Some(70.0)));
+    }
+
+    #[test]
+    fn starter_config_is_written_once() {
+        let dir = TestDir::new("firstrun");
+        let path = dir.join("etc/config.toml");
+        let state = dir.join("state");
+        assert!(is_first_run(&path, &state));
+
+        let s = starter_config(Some((4_200_000, 5_000_000)));
+        assert!(s.contains("(84% health)"));
+        let c = Config::parse(&s).unwrap();
+        assert_eq!((70, 85), (c.low_threshold, c.high_threshold));
+
+        write(&path, &s).unwrap();
+        assert!(!is_first_run(&path, &state));
+        assert!(write(&path, "").is_err());
+        assert_eq!(s, fs::read_to_string(&path).unwrap());
+    }
+}
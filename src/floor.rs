@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::Path;
+
+use crate::ChargeBehaviour;
+
+pub const UPOWER_CONF: &str = "/etc/UPower/UPower.conf";
+
+/// UPower's default `PercentageAction`.
+const UPOWER_DEFAULT_ACTION: i8 = 2;
+
+/// The capacity at which UPower takes its critical action (hibernate or
+/// power off), from `PercentageAction` in its config file.
+pub fn upower_action_level(conf: &Path) -> i8 {
+    fs::read_to_string(conf)
+        .ok()
+        .and_then(|s| {
+            s.lines().find_map(|l| {
+                let (k, v) = l.split_once('=')?;
+                (k.trim() == "PercentageAction")
+                    .then(|| v.trim().parse::<f64>().ok())
+                    .flatten()
+            })
+        })
+        .map_or(UPOWER_DEFAULT_ACTION, |v| v.round().clamp(0.0, 100.0) as i8)
+}
+
+/// Adjust `behaviour` so capacity never goes below `floor`: charge when
+/// already below it, and don't force-discharge at it.
+pub fn apply(cap: i8, behaviour: ChargeBehaviour, floor: i8) -> Option<ChargeBehaviour> {
+    match behaviour {
+        ChargeBehaviour::Auto => None,
+        _ if cap < floor => Some(ChargeBehaviour::Auto),
+        ChargeBehaviour::ForceDischarge if cap <= floor => Some(ChargeBehaviour::InhibitCharge),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::floor::{apply, upower_action_level};
+    use crate::testdir::TestDir;
+    use crate::ChargeBehaviour;
+
+    #[test]
+    fn read_upower_action_level() {
+        let dir = TestDir::new("upower");
+        let conf = dir.join("UPower.conf");
+        assert_eq!(2, upower_action_level(&conf));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            &conf,
+            "[UPower]\n# PercentageAction=9\nPercentageLow=20.0\nPercentageAction=5.0\n",
+        )
+        .unwrap();
+        assert_eq!(5, upower_action_level(&conf));
+    }
+
+    #[test]
+    fn never_go_below_floor() {
+        let fd = ChargeBehaviour::ForceDischarge;
+        let inhibit = ChargeBehaviour::InhibitCharge;
+        assert_eq!(None, apply(8, fd, 7));
+        assert_eq!(Some(inhibit), apply(7, fd, 7));
+        assert_eq!(Some(ChargeBehaviour::Auto), apply(6, fd, 7));
+        assert_eq!(Some(ChargeBehaviour::Auto), apply(6, inhibit, 7));
+        assert_eq!(None, apply(7, inhibit, 7));
+        assert_eq!(None, apply(3, ChargeBehaviour::Auto, 7));
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::schedule::DEFAULT_RATE;
+use crate::ChargeBehaviour;
+
+/// How far back capacity readings are used for the rate.
+const WINDOW_SECS: u64 = 3600;
+
+/// Readings have to span this long before their rate is trusted.
+const MIN_SPAN_SECS: u64 = 10 * 60;
+
+/// Time between forecast points.
+const STEP_MINUTES: u32 = 30;
+
+/// How far ahead the forecast goes.
+const HORIZON_MINUTES: u32 = 180;
+
+/// Expected capacity some time from now.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub minutes: u32,
+    pub capacity: i8,
+}
+
+/// Recent capacity readings taken under the same behaviour and AC state.
+#[derive(Debug, Default)]
+pub struct History {
+    samples: VecDeque<(u64, i8)>,
+    state: Option<(ChargeBehaviour, Option<bool>)>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Readings from before a change of behaviour or AC state are dropped,
+    /// the rate from then doesn't say anything about now. So are readings
+    /// from after `now`, after the clock was set back.
+    pub fn observe(
+        &mut self,
+        now: u64,
+        capacity: i8,
+        behaviour: ChargeBehaviour,
+        ac: Option<bool>,
+    ) {
+        let stepped_back = self.samples.back().is_some_and(|&(t, _)| t > now);
+        if self.state != Some((behaviour, ac)) || stepped_back {
+            self.state = Some((behaviour, ac));
+            self.samples.clear();
+        }
+        self.samples.push_back((now, capacity));
+        while self
+            .samples
+            .front()
+            .is_some_and(|&(t, _)| now.saturating_sub(t) > WINDOW_SECS)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Measured change in percent per hour, if readings span long enough.
+    fn rate(&self) -> Option<f64> {
+        let (&(t0, c0), &(t1, c1)) = (self.samples.front()?, self.samples.back()?);
+        let span = t1.saturating_sub(t0);
+        (span >= MIN_SPAN_SECS).then(|| f64::from(c1 - c0) * 3600.0 / span as f64)
+    }
+
+    /// Expected capacity over the next hours if the behaviour is left to the
+    /// daemon: charging stops at `high`, force-discharge at `until`. Empty
+    /// when there is nothing to go by.
+    pub fn forecast(
+        &self,
+        capacity: i8,
+        behaviour: ChargeBehaviour,
+        ac: Option<bool>,
+        high: i8,
+        until: i8,
+    ) -> Vec<Point> {
+        let (rate, bound) = match (behaviour, ac) {
+            (_, Some(false)) => match self.rate() {
+                Some(r) => (r.min(0.0), 0),
+                None => return Vec::new(),
+            },
+            (ChargeBehaviour::InhibitCharge, _) => (0.0, capacity),
+            (ChargeBehaviour::Auto, _) if capacity >= high => (0.0, capacity),
+            (ChargeBehaviour::Auto, _) => (self.rate().unwrap_or(DEFAULT_RATE).max(0.0), high),
+            (ChargeBehaviour::ForceDischarge, _) => match self.rate() {
+                Some(r) => (r.min(0.0), until.min(capacity)),
+                None => return Vec::new(),
+            },
+        };
+        (1..=HORIZON_MINUTES / STEP_MINUTES)
+            .map(|i| {
+                let minutes = i * STEP_MINUTES;
+                let c = f64::from(capacity) + rate * f64::from(minutes) / 60.0;
+                let c = if rate >= 0.0 {
+                    c.min(f64::from(bound.max(capacity)))
+                } else {
+                    c.max(f64::from(bound))
+                };
+                Point {
+                    minutes,
+                    capacity: c.round() as i8,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::forecast::{History, Point};
+    use crate::schedule::DEFAULT_RATE;
+    use crate::ChargeBehaviour;
+
+    fn capacities(f: &[Point]) -> Vec<i8> {
+        f.iter().map(|p| p.capacity).collect()
+    }
+
+    #[test]
+    fn charging_levels_off_at_high_threshold() {
+        let mut h = History::new();
+        h.observe(0, 60, ChargeBehaviour::Auto, Some(true));
+        let f = h.forecast(60, ChargeBehaviour::Auto, Some(true), 80, 80);
+        assert_eq!(6, f.len());
+        // Nothing measured yet, so the default rate.
+        assert_eq!(
+            (30, 60 + DEFAULT_RATE as i8 / 2),
+            (f[0].minutes, f[0].capacity)
+        );
+        assert_eq!(80, f[5].capacity);
+
+        h.observe(1800, 75, ChargeBehaviour::Auto, Some(true));
+        let f = h.forecast(75, ChargeBehaviour::Auto, Some(true), 80, 80);
+        assert_eq!(vec![80; 6], capacities(&f));
+    }
+
+    #[test]
+    fn on_battery_follows_measured_drain() {
+        let mut h = History::new();
+        h.observe(0, 80, ChargeBehaviour::Auto, Some(false));
+        assert!(h
+            .forecast(80, ChargeBehaviour::Auto, Some(false), 80, 80)
+            .is_empty());
+        h.observe(3600, 70, ChargeBehaviour::Auto, Some(false));
+        let f = h.forecast(70, ChargeBehaviour::Auto, Some(false), 80, 80);
+        assert_eq!(vec![65, 60, 55, 50, 45, 40], capacities(&f));
+
+        // Plugging in starts over.
+        h.observe(3700, 70, ChargeBehaviour::InhibitCharge, Some(true));
+        assert_eq!(None, h.rate());
+    }
+
+    #[test]
+    fn clock_going_back_starts_over() {
+        let mut h = History::new();
+        h.observe(7200, 80, ChargeBehaviour::Auto, Some(false));
+        h.observe(10800, 70, ChargeBehaviour::Auto, Some(false));
+        assert_eq!(Some(-10.0), h.rate());
+        h.observe(3600, 69, ChargeBehaviour::Auto, Some(false));
+        assert_eq!(None, h.rate());
+        h.observe(7200, 64, ChargeBehaviour::Auto, Some(false));
+        assert_eq!(Some(-5.0), h.rate());
+    }
+}
@@ -0,0 +1,23 @@
+use std::path::Path;
+
+use crate::clock;
+use crate::state::RequestFile;
+
+/// Longest a full charge may be given to finish, a week.
+pub const MAX_TIMEOUT_HOURS: u64 = 7 * 24;
+
+const FILE: RequestFile = RequestFile::new("full-charge");
+
+/// Ask the running daemon to charge to full once, giving up after `timeout_secs`.
+pub fn request(dir: &Path, timeout_secs: u64) -> Result<(), anyhow::Error> {
+    FILE.write(dir, &(clock::now() + timeout_secs).to_string())
+}
+
+/// Deadline (seconds since the epoch) of the pending full charge, if any.
+pub fn pending(dir: &Path) -> Result<Option<u64>, anyhow::Error> {
+    Ok(FILE.read(dir)?.map(|s| s.parse()).transpose()?)
+}
+
+pub fn clear(dir: &Path) -> Result<(), anyhow::Error> {
+    FILE.clear(dir)
+}
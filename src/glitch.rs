@@ -0,0 +1,66 @@
+use log::{info, warn};
+
+/// Capacity changes larger than this in one interval are treated as suspect.
+const MAX_JUMP: i16 = 20;
+
+/// Holds back sudden capacity jumps, as reported by a glitching fuel gauge,
+/// until they are confirmed by the following read.
+#[derive(Debug, Default)]
+pub struct GlitchFilter {
+    last: Option<i8>,
+    suspect: Option<i8>,
+}
+
+impl GlitchFilter {
+    /// Returns the capacity to act on for a raw reading.
+    pub fn filter(&mut self, cap: i8) -> i8 {
+        let close = |a: i8, b: i8| (i16::from(a) - i16::from(b)).abs() <= MAX_JUMP;
+        match (self.last, self.suspect) {
+            (Some(last), _) if close(cap, last) => {}
+            (Some(_), Some(suspect)) if close(cap, suspect) => {
+                info!("Capacity jump to {cap}% confirmed");
+            }
+            (Some(last), _) => {
+                warn!("Capacity jumped from {last}% to {cap}%, holding {last}% until confirmed");
+                self.suspect = Some(cap);
+                return last;
+            }
+            (None, _) => {}
+        }
+        self.last = Some(cap);
+        self.suspect = None;
+        cap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::glitch::GlitchFilter;
+
+    #[test]
+    fn single_glitch_is_ignored() {
+        let mut f = GlitchFilter::default();
+        assert_eq!(75, f.filter(75));
+        assert_eq!(75, f.filter(0));
+        assert_eq!(76, f.filter(76));
+        assert_eq!(76, f.filter(100));
+        assert_eq!(77, f.filter(77));
+    }
+
+    #[test]
+    fn confirmed_jump_is_accepted() {
+        let mut f = GlitchFilter::default();
+        assert_eq!(80, f.filter(80));
+        assert_eq!(80, f.filter(50));
+        assert_eq!(51, f.filter(51));
+        assert_eq!(52, f.filter(52));
+    }
+
+    #[test]
+    fn normal_changes_pass_through() {
+        let mut f = GlitchFilter::default();
+        assert_eq!(5, f.filter(5));
+        assert_eq!(25, f.filter(25));
+        assert_eq!(24, f.filter(24));
+    }
+}
@@ -0,0 +1,91 @@
+use std::fmt::Display;
+
+use crate::clock::format_day;
+
+/// Battery health (full charge capacity as a percentage of design) on a given day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthPoint {
+    pub day: i64,
+    pub percent: f64,
+}
+
+/// Estimate of when the battery will need replacing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Forecast {
+    pub current: f64,
+    /// Change in health per 30 days, negative when declining.
+    pub per_month: f64,
+    /// Day the health is expected to cross the threshold, if it is declining.
+    pub replace_on: Option<i64>,
+}
+
+impl Display for Forecast {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "health={:.1}% trend={:+.2}%/month",
+            self.current, self.per_month
+        )?;
+        match self.replace_on {
+            Some(day) => write!(f, " replace_by={}", format_day(day)),
+            None => write!(f, " replace_by=-"),
+        }
+    }
+}
+
+/// Fit a least-squares line through the points and find where it crosses
+/// `threshold` percent. Needs at least two distinct days.
+pub fn forecast(points: &[HealthPoint], threshold: f64) -> Option<Forecast> {
+    let n = points.len() as f64;
+    let last = points.iter().max_by_key(|p| p.day)?;
+    let mean_x = points.iter().map(|p| p.day as f64).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.percent).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.day as f64 - mean_x).powi(2)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let sxy: f64 = points
+        .iter()
+        .map(|p| (p.day as f64 - mean_x) * (p.percent - mean_y))
+        .sum();
+    let slope = sxy / sxx;
+    let intercept = mean_y - slope * mean_x;
+    let replace_on = if slope < 0.0 {
+        let day = ((threshold - intercept) / slope).ceil() as i64;
+        Some(day.max(last.day))
+    } else {
+        None
+    };
+    Some(Forecast {
+        current: last.percent,
+        per_month: slope * 30.0,
+        replace_on,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::health::{forecast, HealthPoint};
+
+    fn points(ps: &[(i64, f64)]) -> Vec<HealthPoint> {
+        ps.iter()
+            .map(|&(day, percent)| HealthPoint { day, percent })
+            .collect()
+    }
+
+    #[test]
+    fn linear_decline_is_extrapolated() {
+        let f = forecast(&points(&[(0, 90.0), (10, 89.0), (20, 88.0)]), 80.0).unwrap();
+        assert_eq!(88.0, f.current);
+        assert!((f.per_month + 3.0).abs() < 1e-9);
+        assert_eq!(Some(100), f.replace_on);
+    }
+
+    #[test]
+    fn no_replacement_date_without_decline() {
+        let f = forecast(&points(&[(0, 90.0), (10, 90.5)]), 80.0).unwrap();
+        assert_eq!(None, f.replace_on);
+        assert!(forecast(&points(&[(3, 90.0)]), 80.0).is_none());
+        assert!(forecast(&[], 80.0).is_none());
+    }
+}
@@ -0,0 +1,288 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use log::{debug, info, warn};
+use serde::Deserialize;
+
+use crate::wake::Wake;
+use crate::{drain, full, state, status, thresholds};
+
+/// Time a client gets to send its whole request.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest request line and headers accepted, together.
+const MAX_HEAD: u64 = 8192;
+
+/// Largest request body accepted.
+const MAX_BODY: usize = 4096;
+
+/// Connections handled at once, more are closed right away.
+const MAX_CONNECTIONS: usize = 8;
+
+/// A reader that fails once `deadline` has passed, so a client sending a
+/// byte at a time can't hold on to a connection.
+struct Deadline<R> {
+    inner: R,
+    deadline: Instant,
+}
+
+impl<R: Read> Read for Deadline<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if Instant::now() >= self.deadline {
+            return Err(std::io::Error::new(
+                ErrorKind::TimedOut,
+                "Request took too long",
+            ));
+        }
+        self.inner.read(buf)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ThresholdsBody {
+    low: i8,
+    high: i8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FullChargeBody {
+    timeout_hours: u64,
+}
+
+impl Default for FullChargeBody {
+    fn default() -> Self {
+        // The same as `full-charge` without --timeout.
+        Self { timeout_hours: 8 }
+    }
+}
+
+/// Where to listen, from `HOST:PORT` or just a port on the loopback address.
+pub fn parse_addr(s: &str) -> Result<SocketAddr, anyhow::Error> {
+    let s = if s.parse::<u16>().is_ok() {
+        format!("127.0.0.1:{s}")
+    } else {
+        s.to_string()
+    };
+    s.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Could not resolve {s}"))
+}
+
+/// Serve the control API on `addr` in the background. Requests are made
+/// the same way the command line makes them, then the daemon is woken
+/// with a [`Wake::Request`].
+pub fn serve(addr: SocketAddr, wake: Sender<Wake>) -> Result<(), anyhow::Error> {
+    let listener = TcpListener::bind(addr)?;
+    if !addr.ip().is_loopback() {
+        warn!("Control API on {addr} is reachable from other machines, and has no authentication");
+    }
+    info!("Serving the control API on http://{addr}");
+    let active = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if active.load(Ordering::Relaxed) >= MAX_CONNECTIONS {
+                debug!("Too many control API connections, closing a new one");
+                continue;
+            }
+            active.fetch_add(1, Ordering::Relaxed);
+            let (active, wake) = (active.clone(), wake.clone());
+            thread::spawn(move || {
+                if let Err(e) = handle(stream, &wake) {
+                    debug!("Control API request failed: {e:#}");
+                }
+                active.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(mut stream: TcpStream, wake: &Sender<Wake>) -> Result<(), anyhow::Error> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(Deadline {
+        inner: stream.try_clone()?,
+        deadline: Instant::now() + TIMEOUT,
+    })
+    .take(MAX_HEAD);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = (
+        parts.next().unwrap_or_default().to_string(),
+        parts.next().unwrap_or_default().to_string(),
+    );
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse()?;
+            }
+        }
+    }
+    let (code, body) = if reader.limit() == 0 {
+        (431, error("Request headers too large"))
+    } else if length > MAX_BODY {
+        (413, error("Request body too large"))
+    } else {
+        reader.set_limit(length as u64);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body)?;
+        route(&state::state_dir(), &method, &path, &body)
+    };
+    if (200..300).contains(&code) && method != "GET" {
+        info!("{method} {path} requested over the control API");
+        let _ = wake.send(Wake::Request);
+    }
+    let reason = match code {
+        200 => "OK",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {code} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    if matches!(code, 413 | 431) {
+        // Closing with the rest of the request unread would reset the
+        // connection before the client sees the answer.
+        stream.shutdown(Shutdown::Write)?;
+        reader.set_limit(MAX_HEAD + MAX_BODY as u64);
+        let _ = std::io::copy(&mut reader, &mut std::io::sink());
+    }
+    Ok(())
+}
+
+fn error(msg: impl std::fmt::Display) -> String {
+    serde_json::json!({ "error": msg.to_string() }).to_string()
+}
+
+/// Status code and JSON body answering a request.
+fn route(dir: &Path, method: &str, path: &str, body: &[u8]) -> (u16, String) {
+    let result = match (method, path) {
+        ("GET", "/status") => match status::read(dir) {
+            Ok(Some(s)) => return (200, serde_json::to_string(&s).unwrap_or_default()),
+            Ok(None) => return (503, error("The daemon hasn't written a status yet")),
+            Err(e) => return (500, error(format!("{e:#}"))),
+        },
+        ("PUT", "/thresholds") => serde_json::from_slice::<ThresholdsBody>(body)
+            .map_err(anyhow::Error::from)
+            .and_then(|t| thresholds::request(dir, t.low, t.high)),
+        ("DELETE", "/thresholds") => thresholds::clear(dir),
+        ("POST", "/full-charge") => {
+            let req = if body.iter().all(u8::is_ascii_whitespace) {
+                Ok(FullChargeBody::default())
+            } else {
+                serde_json::from_slice::<FullChargeBody>(body)
+            };
+            req.map_err(anyhow::Error::from).and_then(|req| {
+                if !(1..=full::MAX_TIMEOUT_HOURS).contains(&req.timeout_hours) {
+                    return Err(anyhow!(
+                        "timeout_hours must be between 1 and {}",
+                        full::MAX_TIMEOUT_HOURS
+                    ));
+                }
+                drain::clear(dir)?;
+                full::request(dir, req.timeout_hours * 60 * 60)
+            })
+        }
+        (_, "/status" | "/thresholds" | "/full-charge") => {
+            return (405, error(format!("{method} is not supported on {path}")))
+        }
+        _ => return (404, error(format!("No such endpoint {path}"))),
+    };
+    match result {
+        Ok(()) if method == "POST" => (202, String::new()),
+        Ok(()) => (204, String::new()),
+        Err(e) => (400, error(format!("{e:#}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use crate::http::{handle, parse_addr, route};
+    use crate::testdir::TestDir;
+    use crate::{full, thresholds};
+
+    #[test]
+    fn listens_on_loopback_by_default() {
+        assert_eq!("127.0.0.1:8787", parse_addr("8787").unwrap().to_string());
+        assert_eq!("0.0.0.0:80", parse_addr("0.0.0.0:80").unwrap().to_string());
+    }
+
+    #[test]
+    fn refuses_huge_headers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let (tx, _rx) = mpsc::channel();
+        let request = format!(
+            "GET /status HTTP/1.1\r\nX-Junk: {}\r\n\r\n",
+            "a".repeat(10_000)
+        );
+        client.write_all(request.as_bytes()).unwrap();
+        let server = thread::spawn(move || handle(server, &tx).unwrap());
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 "), "{response}");
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn routes_requests() {
+        let dir = TestDir::new("http");
+        assert_eq!(503, route(&dir, "GET", "/status", b"").0);
+        assert_eq!(
+            204,
+            route(&dir, "PUT", "/thresholds", br#"{"low": 60, "high": 80}"#).0
+        );
+        assert_eq!(Some((60, 80)), thresholds::pending(&dir).unwrap());
+        assert_eq!(
+            400,
+            route(&dir, "PUT", "/thresholds", br#"{"low": 80, "high": 60}"#).0
+        );
+        assert_eq!(204, route(&dir, "DELETE", "/thresholds", b"").0);
+        assert_eq!(None, thresholds::pending(&dir).unwrap());
+        assert_eq!(202, route(&dir, "POST", "/full-charge", b"").0);
+        assert!(full::pending(&dir).unwrap().is_some());
+        assert_eq!(
+            400,
+            route(
+                &dir,
+                "POST",
+                "/full-charge",
+                br#"{"timeout_hours": 18446744073709551615}"#
+            )
+            .0
+        );
+        assert_eq!(405, route(&dir, "GET", "/full-charge", b"").0);
+        assert_eq!(404, route(&dir, "GET", "/", b"").0);
+    }
+}
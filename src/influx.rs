@@ -0,0 +1,292 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::anyhow;
+use log::warn;
+
+use crate::events::{Event, Subscriber};
+use crate::reason::Override;
+use crate::state;
+
+/// Time an HTTP write may take.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where to send InfluxDB line protocol samples.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Target {
+    Udp(String),
+    /// `HOST:PORT` and the path with query, e.g. `/write?db=battery`.
+    Http(String, String),
+    File(PathBuf),
+}
+
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(addr) = s.strip_prefix("udp://") {
+            Ok(Self::Udp(addr.to_string()))
+        } else if let Some(rest) = s.strip_prefix("http://") {
+            let (addr, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            let addr = if addr.contains(':') {
+                addr.to_string()
+            } else {
+                format!("{addr}:80")
+            };
+            let path = if path.is_empty() { "/" } else { path };
+            Ok(Self::Http(addr, path.to_string()))
+        } else if let Some(path) = s.strip_prefix("file://") {
+            Ok(Self::File(PathBuf::from(path)))
+        } else {
+            Err(anyhow!(
+                "Unknown InfluxDB target {s}, expected udp://HOST:PORT, http://HOST[:PORT]/PATH or file:///PATH"
+            ))
+        }
+    }
+}
+
+/// Writes every reading and transition as InfluxDB line protocol.
+pub struct InfluxExporter {
+    target: Target,
+    socket: Option<UdpSocket>,
+    /// Lines for the HTTP writer thread.
+    http: Option<Sender<String>>,
+    host: String,
+    /// Reason of the override in effect, if any.
+    active_override: Option<Override>,
+    /// Active profile, if any.
+    profile: Option<String>,
+}
+
+impl InfluxExporter {
+    /// Lines are tagged with `instance` as the host.
+    pub fn new(target: Target, instance: String) -> Result<Self, anyhow::Error> {
+        let socket = match &target {
+            Target::Udp(addr) => {
+                let dest = addr
+                    .to_socket_addrs()?
+                    .next()
+                    .ok_or_else(|| anyhow!("Could not resolve {addr}"))?;
+                let s = UdpSocket::bind(if dest.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                })?;
+                s.connect(dest)?;
+                Some(s)
+            }
+            _ => None,
+        };
+        let http = match &target {
+            Target::Http(addr, path) => {
+                // Writes are made in the background, a slow server mustn't
+                // hold up the control loop.
+                let (tx, rx) = mpsc::channel::<String>();
+                let (addr, path) = (addr.clone(), path.clone());
+                thread::spawn(move || {
+                    for line in rx {
+                        if let Err(e) = post(&addr, &path, &line) {
+                            warn!("Could not export InfluxDB sample: {e}");
+                        }
+                    }
+                });
+                Some(tx)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            target,
+            socket,
+            http,
+            host: instance,
+            active_override: None,
+            profile: None,
+        })
+    }
+
+    fn send(&self, line: &str) -> Result<(), anyhow::Error> {
+        match (&self.target, &self.socket, &self.http) {
+            (Target::Udp(_), Some(s), _) => {
+                s.send(format!("{line}\n").as_bytes())?;
+            }
+            (Target::Http(..), _, Some(tx)) => tx.send(line.to_string())?,
+            (Target::File(path), ..) => state::append_line(path, line)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Write one line to an InfluxDB HTTP write endpoint.
+fn post(addr: &str, path: &str, line: &str) -> Result<(), anyhow::Error> {
+    let dest = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("Could not resolve {addr}"))?;
+    let mut stream = TcpStream::connect_timeout(&dest, HTTP_TIMEOUT)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    let body = format!("{line}\n");
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\nHost: {addr}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(anyhow!("{addr} answered {:?}", status.trim())),
+    }
+}
+
+/// Escape a tag value for line protocol.
+fn escape_tag(s: &str) -> String {
+    s.replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+/// Format an event as a line, or `None` for events that aren't exported.
+/// Lines are tagged with the host, and the active override and profile (or
+/// `none`).
+fn format_line(
+    event: &Event,
+    host: &str,
+    active_override: Option<&str>,
+    profile: Option<&str>,
+    timestamp_ns: u128,
+) -> Option<String> {
+    let fields = match event {
+        Event::CapacityRead {
+            capacity,
+            behaviour,
+            ac_online,
+            energy,
+            ..
+        } => {
+            let mut fields = format!("capacity={capacity}i,behaviour=\"{behaviour}\"");
+            if let Some(online) = ac_online {
+                fields += &format!(",ac_online={online}");
+            }
+            if let Some(energy) = energy {
+                fields += &format!(",energy_uwh={energy}i");
+            }
+            ("macsmc_battery", fields)
+        }
+        Event::TransitionApplied {
+            old,
+            new,
+            capacity,
+            reason,
+        } => (
+            "macsmc_transition",
+            format!(
+                "old=\"{old}\",new=\"{new}\",capacity={capacity}i,reason=\"{reason}\",kind=\"{}\"",
+                reason.kind()
+            ),
+        ),
+        _ => return None,
+    };
+    Some(format!(
+        "{},host={},override={},profile={} {} {timestamp_ns}",
+        fields.0,
+        escape_tag(host),
+        escape_tag(active_override.unwrap_or("none")),
+        escape_tag(profile.unwrap_or("none")),
+        fields.1
+    ))
+}
+
+impl Subscriber for InfluxExporter {
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::OverrideSet { behaviour, reason } => {
+                self.active_override = behaviour.map(|_| *reason);
+            }
+            Event::ProfileChanged { name } => self.profile = name.clone(),
+            _ => {}
+        }
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        if let Some(line) = format_line(
+            event,
+            &self.host,
+            self.active_override.map(Override::name),
+            self.profile.as_deref(),
+            ts,
+        ) {
+            if let Err(e) = self.send(&line) {
+                warn!("Could not export InfluxDB sample: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::events::Event;
+    use crate::influx::{format_line, Target};
+    use crate::reason::Reason;
+    use crate::ChargeBehaviour;
+
+    #[test]
+    fn parse_targets() {
+        assert_eq!(
+            Target::Udp("localhost:8089".to_string()),
+            "udp://localhost:8089".parse().unwrap()
+        );
+        assert_eq!(
+            Target::File(PathBuf::from("/tmp/samples.lp")),
+            "file:///tmp/samples.lp".parse().unwrap()
+        );
+        assert_eq!(
+            Target::Http("influx:8086".to_string(), "/write?db=battery".to_string()),
+            "http://influx:8086/write?db=battery".parse().unwrap()
+        );
+        assert_eq!(
+            Target::Http("influx:80".to_string(), "/".to_string()),
+            "http://influx".parse().unwrap()
+        );
+        assert!("https://influx".parse::<Target>().is_err());
+    }
+
+    #[test]
+    fn format_events_as_line_protocol() {
+        let read = Event::CapacityRead {
+            capacity: 75,
+            behaviour: ChargeBehaviour::Auto,
+            ac_online: Some(true),
+            energy: Some(50_000_000),
+            charge_full: None,
+        };
+        assert_eq!(
+            Some("macsmc_battery,host=my\\ mac,override=none,profile=none capacity=75i,behaviour=\"auto\",ac_online=true,energy_uwh=50000000i 1000".to_string()),
+            format_line(&read, "my mac", None, None, 1000)
+        );
+
+        let transition = Event::TransitionApplied {
+            old: ChargeBehaviour::Auto,
+            new: ChargeBehaviour::InhibitCharge,
+            capacity: 80,
+            reason: Reason::WithinThresholds,
+        };
+        assert_eq!(
+            Some("macsmc_transition,host=mac,override=travel,profile=desk old=\"auto\",new=\"inhibit-charge\",capacity=80i,reason=\"within thresholds\",kind=\"within_thresholds\" 5".to_string()),
+            format_line(&transition, "mac", Some("travel"), Some("desk"), 5)
+        );
+
+        assert_eq!(
+            None,
+            format_line(&Event::AcChanged { online: true }, "mac", None, None, 5)
+        );
+    }
+}
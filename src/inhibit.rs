@@ -0,0 +1,98 @@
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+
+use log::{info, warn};
+
+use crate::events::{Event, Subscriber};
+use crate::reason::Override;
+
+/// A logind sleep inhibitor, held for as long as this value lives.
+///
+/// Taken by running `systemd-inhibit` around a process that never exits, so
+/// no D-Bus client is needed. Both run in their own process group, and
+/// dropping this kills the group, which releases the lock.
+pub struct Inhibitor {
+    child: Child,
+}
+
+impl Inhibitor {
+    pub fn take(why: &str) -> Result<Self, anyhow::Error> {
+        let child = Command::new("systemd-inhibit")
+            .args([
+                "--what=sleep:idle",
+                "--who=macsmc-charged",
+                &format!("--why={why}"),
+                "--mode=block",
+                "sleep",
+                "infinity",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .process_group(0)
+            .spawn()?;
+        Ok(Self { child })
+    }
+}
+
+impl Drop for Inhibitor {
+    fn drop(&mut self) {
+        // Killing only systemd-inhibit would leave its sleep running forever.
+        if let Ok(pgid) = libc::pid_t::try_from(self.child.id()) {
+            // SAFETY: the group is the child's own, and it isn't reaped
+            // until the wait below, so the id can't have been reused.
+            unsafe {
+                libc::killpg(pgid, libc::SIGTERM);
+            }
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// Blocks suspend while a drain or a calibration cycle is in progress, so it
+/// isn't cut short halfway through. The charge to full is part of a
+/// calibration too, sleeping through it only drags the cycle out.
+#[derive(Default)]
+pub struct SleepInhibitor {
+    drain: Option<Inhibitor>,
+    calibration: Option<Inhibitor>,
+}
+
+impl SleepInhibitor {
+    fn hold(held: &mut Option<Inhibitor>, wanted: bool, why: &str) {
+        match (wanted, held.is_some()) {
+            (true, false) => match Inhibitor::take(why) {
+                Ok(i) => {
+                    info!("Blocking sleep: {why}");
+                    *held = Some(i);
+                }
+                Err(e) => warn!("Could not take sleep inhibitor: {e}"),
+            },
+            (false, true) => *held = None,
+            _ => {}
+        }
+    }
+}
+
+impl Subscriber for SleepInhibitor {
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::OverrideSet {
+                behaviour,
+                reason: Override::Drain,
+            } => Self::hold(
+                &mut self.drain,
+                behaviour.is_some(),
+                "Draining battery to a target level",
+            ),
+            Event::OverrideSet {
+                behaviour,
+                reason: Override::Calibration,
+            } => Self::hold(
+                &mut self.calibration,
+                behaviour.is_some(),
+                "Calibrating the battery",
+            ),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::io::Write;
+use std::os::unix::net::UnixDatagram;
+
+use env_logger::filter::Filter;
+use log::kv::{Key, Value, VisitSource};
+use log::{Level, Log, Metadata, Record};
+
+/// Where journald takes entries in its native protocol.
+const SOCKET: &str = "/run/systemd/journal/socket";
+
+/// A logger sending entries straight to journald, with the key-values of a
+/// log call as extra fields, e.g. `journalctl NEW_BEHAVIOUR=force-discharge`.
+pub struct JournalLogger {
+    socket: UnixDatagram,
+    filter: Filter,
+}
+
+impl JournalLogger {
+    /// Connect to the journal, or fail if it isn't running.
+    pub fn new(filter: Filter) -> Result<Self, std::io::Error> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(SOCKET)?;
+        Ok(Self { socket, filter })
+    }
+
+    /// Install as the global logger.
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(self.filter.filter());
+        log::set_boxed_logger(Box::new(self))
+    }
+}
+
+impl Log for JournalLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.filter.matches(record) {
+            return;
+        }
+        let entry = encode(record);
+        if self.socket.send(&entry).is_err() {
+            // Too large or the journal went away, don't lose the message.
+            let _ = writeln!(std::io::stderr(), "{}: {}", record.target(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// A journal field name from a log key: upper case letters, digits and
+/// underscores, not starting with an underscore or a digit.
+fn field_name(key: &str) -> String {
+    let name: String = key
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect();
+    match name.chars().next() {
+        Some('A'..='Z') => name,
+        _ => format!("F{name}"),
+    }
+}
+
+fn add_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        // Values with newlines are sent with their length instead of `=`.
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+struct Fields<'a>(&'a mut Vec<u8>);
+
+impl<'kvs> VisitSource<'kvs> for Fields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        add_field(self.0, &field_name(key.as_str()), &value.to_string());
+        Ok(())
+    }
+}
+
+/// One journal entry in the native protocol.
+fn encode(record: &Record) -> Vec<u8> {
+    let mut buf = Vec::new();
+    add_field(&mut buf, "MESSAGE", &record.args().to_string());
+    add_field(&mut buf, "PRIORITY", &priority(record.level()).to_string());
+    add_field(&mut buf, "SYSLOG_IDENTIFIER", env!("CARGO_PKG_NAME"));
+    add_field(&mut buf, "TARGET", record.target());
+    if let Some(file) = record.file() {
+        add_field(&mut buf, "CODE_FILE", file);
+    }
+    if let Some(line) = record.line() {
+        add_field(&mut buf, "CODE_LINE", &line.to_string());
+    }
+    let _ = record.key_values().visit(&mut Fields(&mut buf));
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, Record};
+
+    use crate::journal::{encode, field_name};
+
+    #[test]
+    fn encodes_fields() {
+        let kvs = [("capacity", 85), ("new-behaviour", 1)];
+        let record = Record::builder()
+            .args(format_args!("two\nlines"))
+            .level(Level::Warn)
+            .target("macsmc_charged")
+            .key_values(&kvs)
+            .build();
+        let entry = encode(&record);
+
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\nPRIORITY=4\nSYSLOG_IDENTIFIER=macsmc-charged\nTARGET=macsmc_charged\nCAPACITY=85\nNEW_BEHAVIOUR=1\n");
+        assert_eq!(expected, entry);
+
+        assert_eq!("OLD_BEHAVIOUR", field_name("old_behaviour"));
+        assert_eq!("F_X", field_name("_x"));
+    }
+}
@@ -1,17 +1,133 @@
 use std::fmt::Display;
 use std::io::Write;
-use std::{fs, str::FromStr, thread::sleep, time::Duration};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Instant;
+use std::{str::FromStr, thread::sleep, time::Duration};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
+use audit::AuditLog;
+use backoff::WriteBackoff;
+use calibration::{Calibration, Phase};
+use cli::{Action, Cli, Format};
+use config::{Config, LogStyle};
+use discharge::DischargeWatch;
 use env_logger::Env;
-use log::{debug, info};
+use events::{Event, EventBus};
+use external::ExternalChange;
+use firmware::FirmwareLimitDetector;
+use glitch::GlitchFilter;
+use influx::InfluxExporter;
+use inhibit::SleepInhibitor;
+use journal::JournalLogger;
+use log::{debug, info, trace, warn};
+use maintenance::Window;
+use mqtt::{Broker, MqttPublisher};
+use reason::{Failsafe, Override, Reason};
+use recent::RecentEvents;
+use schedule::{FullBy, Schedule};
+use serde::Deserialize;
+use sessions::{PlugLog, SessionTracker};
+use snapshot::Snapshot;
+use stack::{Priority, Stack};
+use status::{StackEntry, Status};
+use summary::SummaryRecorder;
+use thermal::ThermalGuard;
+use travel::Trip;
+use wake::Wake;
+
+mod audit;
+mod backoff;
+mod calibration;
+mod charger;
+mod cli;
+mod clock;
+mod config;
+mod crash;
+mod credentials;
+mod discharge;
+mod drain;
+mod events;
+mod external;
+mod firmware;
+mod firstrun;
+mod floor;
+mod forecast;
+mod full;
+mod glitch;
+mod health;
+mod http;
+mod influx;
+mod inhibit;
+mod journal;
+mod maintenance;
+mod memory;
+mod mqtt;
+mod profile;
+mod readiness;
+mod reason;
+mod recent;
+mod report;
+mod schedule;
+mod sessions;
+mod snapshot;
+mod stack;
+mod state;
+mod status;
+mod storage;
+mod summary;
+mod sysfs;
+#[cfg(test)]
+mod testdir;
+mod thermal;
+mod thresholds;
+mod trace;
+mod travel;
+mod uevent;
+mod update;
+mod upower;
+mod wake;
 
 const LOW_THRESHOLD: i8 = 70;
 const HIGH_THRESHOLD: i8 = 80;
 
+/// Inconsistent snapshots in a row to skip before acting on them anyway.
+const MAX_TORN_READS: u32 = 3;
+
+/// Timed out battery reads in a row after which it's reported as hung.
+const MAX_STALLED_READS: u32 = 3;
+
+/// How long to let a burst of uevents settle before evaluating.
+const UEVENT_SETTLE: Duration = Duration::from_secs(1);
+
 fn main() -> Result<(), anyhow::Error> {
-    match std::env::var("RUST_LOG_STYLE") {
-        Ok(s) if s == "SYSTEMD" => env_logger::builder()
+    let cli = Cli::parse();
+    let config_path = &cli.config;
+    let loaded = Config::load(config_path)?;
+    let mut config = loaded.clone().unwrap_or_default();
+    cli.apply(&mut config)?;
+
+    let style = match std::env::var("RUST_LOG_STYLE") {
+        Ok(s) if s == "SYSTEMD" => LogStyle::Systemd,
+        Ok(s) if s == "JOURNALD" => LogStyle::Journald,
+        Ok(_) => LogStyle::Default,
+        Err(_) => config.log.style,
+    };
+    let env = Env::default().default_filter_or(config.log.level.as_str());
+    let mut journal_error = None;
+    if style == LogStyle::Journald {
+        let filter = env_logger::filter::Builder::new()
+            .parse(&std::env::var("RUST_LOG").unwrap_or_else(|_| config.log.level.clone()))
+            .build();
+        match JournalLogger::new(filter) {
+            Ok(logger) => logger.init()?,
+            Err(e) => journal_error = Some(e),
+        }
+    }
+    match style {
+        LogStyle::Journald if journal_error.is_none() => {}
+        LogStyle::Systemd | LogStyle::Journald => env_logger::Builder::from_env(env)
             .format(|buf, record| {
                 writeln!(
                     buf,
@@ -28,63 +144,1335 @@ fn main() -> Result<(), anyhow::Error> {
                 )
             })
             .init(),
-        _ => env_logger::Builder::from_env(Env::default().default_filter_or("info")).init(),
+        LogStyle::Default => env_logger::Builder::from_env(env).init(),
     };
+    if let Some(e) = journal_error {
+        warn!("Could not connect to the journal, logging to stderr: {e}");
+    }
+    match loaded {
+        Some(_) => info!("Loaded config from {}", config_path.display()),
+        None if cli.require_config => {
+            return Err(anyhow!(
+                "No config at {}, refusing to start with defaults (--require-config)",
+                config_path.display()
+            ))
+        }
+        None if cli.action == Action::Run => {
+            warn!("No config at {}, using defaults", config_path.display())
+        }
+        None => info!("No config at {}, using defaults", config_path.display()),
+    }
+    for w in config.warnings() {
+        warn!("{w}");
+    }
+    sysfs::configure(cli.sysfs_root.as_deref(), &config.battery, &config.ac);
 
-    info!(
-        "Starting up. Current charge behaviour is {}",
-        get_behaviour()?
-    );
+    match cli.action {
+        Action::Run => {
+            crash::install_panic_hook();
+            let res = run(&cli, config);
+            if let Err(e) = &res {
+                crash::report_fatal(e);
+            }
+            res
+        }
+        Action::Drain(target) => {
+            full::clear(&state::state_dir())?;
+            drain::request(&state::state_dir(), target)?;
+            info!("Requested drain to {target}%");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::Travel(true) => {
+            travel::enable(&state::state_dir())?;
+            info!("Travel mode on, charging fully until the battery is used");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::Travel(false) => {
+            travel::disable(&state::state_dir())?;
+            info!("Travel mode off");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::Storage(true) => {
+            storage::enable(&state::state_dir())?;
+            info!(
+                "Storage mode on, holding the battery at {}%",
+                config.storage_level
+            );
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::Storage(false) => {
+            storage::disable(&state::state_dir())?;
+            info!("Storage mode off");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::FullCharge(hours) => {
+            drain::clear(&state::state_dir())?;
+            full::request(&state::state_dir(), hours * 60 * 60)?;
+            info!("Requested a full charge within {hours}h");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::SetThresholds(Some((low, high))) => {
+            thresholds::request(&state::state_dir(), low, high)?;
+            info!("Requested thresholds {low}-{high}%");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::SetThresholds(None) => {
+            thresholds::clear(&state::state_dir())?;
+            info!("Requested the configured thresholds again");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::Profile(Some(name)) => {
+            profile::select(&state::state_dir(), &config, &name)?;
+            info!("Requested profile {name}");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::Profile(None) => {
+            profile::clear(&state::state_dir())?;
+            info!("Requested the top-level thresholds again");
+            notify_daemon(&config);
+            Ok(())
+        }
+        Action::PreUpdate(minutes) => {
+            if !update::updates_pending(config.pre_update_check.as_deref())? {
+                info!("No updates pending");
+                std::process::exit(1);
+            }
+            let level = config.pre_update_level;
+            let read = || {
+                let s = sysfs::read(sysfs::battery("capacity"))
+                    .context("Could not read battery capacity")?;
+                Ok(s.trim().parse()?)
+            };
+            if read()? < level {
+                let dir = state::state_dir();
+                // Leave a full charge someone else asked for in place.
+                let requested = full::pending(&dir)?.is_none();
+                if requested {
+                    full::request(&dir, minutes * 60)?;
+                    notify_daemon(&config);
+                }
+                info!("Charging to {level}% before updating");
+                let res = update::wait_for(
+                    level,
+                    Duration::from_secs(minutes * 60),
+                    Duration::from_secs(10),
+                    read,
+                );
+                if requested {
+                    full::clear(&dir)?;
+                }
+                res?;
+            }
+            info!("Battery is at least {level}%, ready to update");
+            Ok(())
+        }
+        Action::PrepareSleep => {
+            if let Some(b) = config.sleep_behaviour {
+                let mut bus = EventBus::default();
+                bus.subscribe(Box::new(AuditLog::new(&state::state_dir())));
+                apply_behaviour(&mut bus, b, Reason::Sleep)?;
+            }
+            Ok(())
+        }
+        Action::Simulate(path) => {
+            let entries = trace::load(&path)?;
+            let (low, high) = (config.low_threshold, config.high_threshold);
+            info!(
+                "Replaying {} operations with thresholds {low}-{high}%",
+                entries.len()
+            );
+            for change in trace::replay(&entries, low, high) {
+                println!("{change}");
+            }
+            Ok(())
+        }
+        Action::Status { format, follow } => {
+            let mut last = String::new();
+            let mut warned = false;
+            loop {
+                let s = match recent_status(&config)? {
+                    Some(s) => {
+                        warned = false;
+                        s
+                    }
+                    None => {
+                        if !warned {
+                            warn!("No recent status from the daemon, is it running? Reading sysfs instead");
+                            warned = true;
+                        }
+                        sysfs_status(&config)?
+                    }
+                };
+                let out = match format {
+                    Format::Text => s.to_string(),
+                    Format::Json => serde_json::to_string_pretty(&s)?,
+                    Format::Waybar => s.waybar(),
+                };
+                if out != last {
+                    println!("{out}");
+                    last = out;
+                }
+                if !follow {
+                    return Ok(());
+                }
+                sleep(Duration::from_secs(2));
+            }
+        }
+    }
+}
+
+/// Have the running daemon act on a request right away. If it can't be
+/// signalled, it still picks the request up on its next poll.
+fn notify_daemon(config: &Config) {
+    if !wake::signal_daemon() {
+        info!(
+            "Could not signal the daemon, it picks the request up within {}s",
+            config.interval
+        );
+    }
+}
+
+/// The status written by the daemon, unless it's too old to be from a running one.
+fn recent_status(config: &Config) -> Result<Option<Status>, anyhow::Error> {
+    let dir = state::state_dir();
+    // The daemon writes its status at least every interval.
+    let stale = Duration::from_secs(config.interval * 3).max(Duration::from_secs(180));
+    Ok(status::read(&dir)?.filter(|_| status::age(&dir).is_some_and(|a| a <= stale)))
+}
+
+/// A status read from sysfs, for when the daemon isn't running.
+fn sysfs_status(config: &Config) -> Result<Status, anyhow::Error> {
+    let snap = Snapshot::read()?;
+    Ok(Status {
+        updated: clock::format_timestamp(clock::now()),
+        capacity: snap.capacity,
+        behaviour: snap.behaviour.to_string(),
+        ac_online: snap.ac_online,
+        low_threshold: config.low_threshold,
+        high_threshold: config.high_threshold,
+        active_override: None,
+        profile: None,
+        rss_kib: None,
+        last_transition: None,
+        stack: Vec::new(),
+        firmware_limit: None,
+        health: None,
+        forecast: Vec::new(),
+    })
+}
+
+fn run(cli: &Cli, mut config: Config) -> Result<(), anyhow::Error> {
+    if let Some(path) = &cli.record_trace {
+        trace::start(path)?;
+        info!("Recording sysfs trace to {}", path.display());
+    }
+    let reload = config::reload_on_sighup()?;
+    let stance = config.startup;
+    if config.startup_delay > 0 {
+        let secs = config.startup_delay;
+        info!("Waiting {secs}s before first evaluation");
+        sleep(Duration::from_secs(secs));
+    }
+    if let Some(path) = &config.wait_for {
+        wait_for(path, Duration::from_secs(config.wait_for_timeout))?;
+    }
+    if !sysfs::battery_present() {
+        info!("No battery found, nothing to manage");
+        // Report ready first, so a notify unit stops cleanly instead of failing.
+        readiness::notify_ready()?;
+        readiness::sd_notify("STOPPING=1")?;
+        return Ok(());
+    }
+    if firstrun::is_first_run(&cli.config, &state::state_dir()) {
+        let starter = firstrun::starter_config(firstrun::battery_size());
+        match firstrun::write(&cli.config, &starter) {
+            Ok(()) => {
+                info!(
+                    "First run, created a starter config at {}",
+                    cli.config.display()
+                );
+                config = Config::parse(&starter)?;
+                cli.apply(&mut config)?;
+            }
+            Err(e) => warn!(
+                "First run, could not create a starter config at {}: {e:#}",
+                cli.config.display()
+            ),
+        }
+    }
+    let (mut low, mut high) = (config.low_threshold, config.high_threshold);
+    crash::set_config_summary(format!("{config:#?}"));
+    let original = get_behaviour()?;
+    info!("Starting up ({stance}). Current charge behaviour is {original}");
+    let monitor = config.monitor;
+    if monitor {
+        info!("Running in monitor mode, charge behaviour will not be changed");
+    }
+    let mut bus = EventBus::default();
+    bus.subscribe(Box::new(RecentEvents));
+    recent::dump_on_sigusr1()?;
+    let summary = SummaryRecorder::new(state::state_dir().join("reports"), config.replace_at);
+    let health = summary.health();
+    bus.subscribe(Box::new(summary));
+    bus.subscribe(Box::new(AuditLog::new(&state::state_dir())));
+    bus.subscribe(Box::new(SessionTracker::new(
+        state::state_dir().join("sessions.jsonl"),
+    )));
+    bus.subscribe(Box::new(PlugLog::new(
+        state::state_dir().join("plugs.jsonl"),
+    )));
+    let firmware = FirmwareLimitDetector::new(high);
+    let firmware_limit = firmware.limit();
+    bus.subscribe(Box::new(firmware));
+    if config.inhibit_sleep {
+        bus.subscribe(Box::new(SleepInhibitor::default()));
+    }
+    if let Some(target) = &config.influx {
+        info!("Exporting samples to {target}");
+        bus.subscribe(Box::new(InfluxExporter::new(
+            target.parse()?,
+            config.instance_name(),
+        )?));
+    }
+    let mut first = true;
+    let mut recommended = None;
+    let mut last_ac = None;
+    let mut draining = None;
+    let mut traveling = false;
+    let mut trip = Trip::default();
+    let mut glitch = GlitchFilter::default();
+    let mut backoff = WriteBackoff::default();
+    let mut weak_charger = false;
+    let mut full_charging = false;
+    let mut full_by = full_by_schedule(&config)?;
+    let mut charging_by = false;
+    let mut maintenance = maintenance_windows(&config)?;
+    let mut maintaining = false;
+    let mut reassert = false;
+    let mut external = ExternalChange::new(Duration::from_secs(config.external_grace));
+    let mut history = forecast::History::new();
+    let mut last_transition = None;
+    let mut thermal = config.max_charge_temp.map(ThermalGuard::new);
+    let mut torn = 0;
+    let mut stalled = 0;
+    let mut storing = false;
+    let mut unknown_profile = None;
+    let mut last_profile = None;
+    let mut calibration = calibration_cycle(&config, None)?;
+    let mut hibernate_floor = hibernate_floor_level(&config);
+    let (wake_tx, wake) = mpsc::channel();
+    wake::on_signals(wake_tx.clone())?;
+    if let Some(addr) = &config.http {
+        http::serve(http::parse_addr(addr)?, wake_tx.clone())?;
+    }
+    let mut mqtt = match &config.mqtt {
+        Some(m) => {
+            let broker = Broker::from_config(m)?;
+            info!("Publishing to MQTT broker {broker}");
+            Some(MqttPublisher::start(
+                broker,
+                &config.instance_name(),
+                &config,
+                wake_tx.clone(),
+            ))
+        }
+        None => None,
+    };
+    if config.uevents {
+        match uevent::subscribe(wake_tx) {
+            Ok(()) => info!(
+                "Listening for power_supply uevents, polling every {}s as a fallback",
+                config.interval
+            ),
+            Err(e) => warn!(
+                "Could not listen for uevents, polling every {}s: {e}",
+                config.interval
+            ),
+        }
+    }
+    let watchdog = readiness::watchdog_interval();
+    if let Some(w) = watchdog {
+        info!("Pinging the systemd watchdog every {:?}", w / 2);
+    }
+    let mut discharge = DischargeWatch::new(Duration::from_secs(config.discharge_timeout));
     loop {
-        let cap = get_capacity()?;
-        let be = get_behaviour()?;
-        let be_new = calc_behaviour(cap, &be);
+        if reload.swap(false, Ordering::Relaxed) {
+            match reload_config(cli, &config) {
+                Ok(new) => {
+                    info!(
+                        "Reloaded config from {}: thresholds {}-{}%, interval {}s",
+                        cli.config.display(),
+                        new.low_threshold,
+                        new.high_threshold,
+                        new.interval
+                    );
+                    for w in new.warnings() {
+                        warn!("{w}");
+                    }
+                    if new.full_by != config.full_by {
+                        if charging_by {
+                            charging_by = false;
+                            bus.publish(Event::OverrideSet {
+                                behaviour: None,
+                                reason: Override::FullBy,
+                            });
+                        }
+                        full_by = full_by_schedule(&new)?;
+                    }
+                    if new.maintenance != config.maintenance {
+                        maintenance = maintenance_windows(&new)?;
+                    }
+                    if new.max_charge_temp != config.max_charge_temp {
+                        thermal = new.max_charge_temp.map(ThermalGuard::new);
+                    }
+                    if (new.calibration_weeks, new.calibration_floor)
+                        != (config.calibration_weeks, config.calibration_floor)
+                    {
+                        let running = calibration.take().map(|(c, ..)| c);
+                        if new.calibration_weeks.is_none()
+                            && running.as_ref().is_some_and(|c| c.phase.is_some())
+                        {
+                            info!("Calibration was turned off, stopping the cycle in progress");
+                            bus.publish(Event::OverrideSet {
+                                behaviour: None,
+                                reason: Override::Calibration,
+                            });
+                        }
+                        calibration = calibration_cycle(&new, running)?;
+                    }
+                    if (new.hibernate_margin, new.hibernate_level)
+                        != (config.hibernate_margin, config.hibernate_level)
+                    {
+                        hibernate_floor = hibernate_floor_level(&new);
+                    }
+                    if new.discharge_timeout != config.discharge_timeout {
+                        discharge = DischargeWatch::new(Duration::from_secs(new.discharge_timeout));
+                    }
+                    if new.external_grace != config.external_grace {
+                        external = ExternalChange::new(Duration::from_secs(new.external_grace));
+                    }
+                    config = new;
+                }
+                Err(e) => {
+                    warn!("Could not reload config, keeping the current one: {e:#}");
+                    bus.publish(Event::Error(format!("Could not reload config: {e:#}")));
+                }
+            }
+        }
+        let requested = match thresholds::pending(&state::state_dir()) {
+            Ok(t) => t,
+            Err(e) => {
+                warn!("Could not read thresholds request: {e}");
+                bus.publish(Event::Error(format!(
+                    "Could not read thresholds request: {e}"
+                )));
+                None
+            }
+        };
+        let selected = match profile::active(&state::state_dir()) {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("Could not read active profile: {e}");
+                bus.publish(Event::Error(format!("Could not read active profile: {e}")));
+                None
+            }
+        };
+        let profiled = selected.and_then(|name| match config.profiles.get(&name) {
+            Some(p) => {
+                unknown_profile = None;
+                Some((name, (p.low_threshold, p.high_threshold)))
+            }
+            None => {
+                if unknown_profile.as_ref() != Some(&name) {
+                    warn!("Profile {name:?} is not in the config, using the configured thresholds");
+                    unknown_profile = Some(name);
+                }
+                None
+            }
+        });
+        let configured = (config.low_threshold, config.high_threshold);
+        let desktop = config
+            .upower
+            .then(upower::kernel_thresholds)
+            .flatten()
+            .map(|k| upower::thresholds(k, configured));
+        let stored = storage::active(&state::state_dir());
+        if stored != storing {
+            storing = stored;
+            // Storage mode mostly holds the battery with charging inhibited.
+            bus.publish(Event::OverrideSet {
+                behaviour: stored.then_some(ChargeBehaviour::InhibitCharge),
+                reason: Override::Storage,
+            });
+        }
+        let mut using_profile = None;
+        let (wanted, source) = match (stored, requested, profiled, desktop) {
+            (true, ..) => (
+                storage::thresholds(config.storage_level),
+                thresholds::Source::Storage,
+            ),
+            (false, Some(t), ..) => (t, thresholds::Source::Requested),
+            (false, None, Some((name, t)), _) => {
+                using_profile = Some(name.clone());
+                (t, thresholds::Source::Profile(name))
+            }
+            (false, None, None, Some(t)) => (t, thresholds::Source::Desktop),
+            (false, None, None, None) => (configured, thresholds::Source::Configured),
+        };
+        if wanted != (low, high) {
+            (low, high) = wanted;
+            info!("Using thresholds {low}-{high}% ({source})");
+            bus.publish(Event::ThresholdsChanged { low, high });
+        }
+        if using_profile != last_profile {
+            last_profile = using_profile.clone();
+            bus.publish(Event::ProfileChanged {
+                name: using_profile.clone(),
+            });
+        }
+        // The discharge band belongs to the configured thresholds, overrides
+        // discharge to their own high threshold.
+        let (above, until) = match source {
+            thresholds::Source::Configured => (
+                config.discharge_above.unwrap_or(high),
+                config.discharge_until.unwrap_or(high),
+            ),
+            _ => (high, high),
+        };
+        let snap = match Snapshot::read() {
+            Ok(snap) => {
+                if stalled >= MAX_STALLED_READS {
+                    info!("Battery can be read again");
+                }
+                stalled = 0;
+                snap
+            }
+            Err(e) if timed_out(&e) => {
+                // Hung in the SMC, try again next time rather than exiting.
+                stalled += 1;
+                if stalled == 1 {
+                    warn!("Could not read the battery, skipping this evaluation: {e:#}");
+                } else {
+                    debug!("Could not read the battery, skipping this evaluation: {e:#}");
+                }
+                bus.publish(Event::Error(format!("Could not read the battery: {e:#}")));
+                if stalled == MAX_STALLED_READS {
+                    warn!("Battery reads keep timing out, the SMC may be hung");
+                }
+                match pause(Duration::from_secs(config.interval), watchdog, &wake) {
+                    Some(Wake::Stop) => break,
+                    w => reassert |= matches!(w, Some(Wake::Reregistered | Wake::Resume)),
+                }
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        match snap.inconsistency() {
+            Some(why) if torn < MAX_TORN_READS => {
+                torn += 1;
+                debug!("Inconsistent readings ({why}), reading again: {snap:?}");
+                sleep(UEVENT_SETTLE);
+                continue;
+            }
+            Some(why) => {
+                if torn == MAX_TORN_READS {
+                    warn!("Readings stay inconsistent ({why}), using them anyway");
+                    torn += 1;
+                }
+            }
+            None => torn = 0,
+        }
+        let cap = glitch.filter(snap.capacity);
+        let be = snap.behaviour;
+        let ac_online = snap.ac_online;
+        if let Some(e) = &snap.ac_error {
+            warn!("Could not read AC status: {e}");
+            bus.publish(Event::Error(format!("Could not read AC status: {e}")));
+        }
+        bus.publish(Event::CapacityRead {
+            capacity: cap,
+            behaviour: be,
+            ac_online,
+            energy: snap.energy,
+            charge_full: snap.charge_full,
+        });
+        let ac_steady = ac_online == Some(true) && last_ac == Some(true);
+        if let Some(online) = ac_online {
+            if last_ac.is_some_and(|last| last != online) {
+                bus.publish(Event::AcChanged { online });
+            }
+            last_ac = Some(online);
+        }
+
+        let policy = (
+            calc_discharging(cap, &be, low, high, above, until),
+            policy_reason(cap, low, high),
+        );
+        let mut stack = Stack::new(
+            if using_profile.is_some() {
+                Priority::Profile
+            } else {
+                Priority::Default
+            },
+            policy.0,
+            policy.1,
+        );
+
+        let drain = match drain::pending(&state::state_dir()) {
+            Ok(d) => d,
+            Err(e) => {
+                warn!("Could not read drain request: {e}");
+                bus.publish(Event::Error(format!("Could not read drain request: {e}")));
+                None
+            }
+        };
+        let (be_new, reason) = match drain {
+            Some(target) if cap > target => {
+                if draining != Some(target) {
+                    info!("Draining to {target}%");
+                    bus.publish(Event::OverrideSet {
+                        behaviour: Some(ChargeBehaviour::ForceDischarge),
+                        reason: Override::Drain,
+                    });
+                }
+                draining = Some(target);
+                (
+                    ChargeBehaviour::ForceDischarge,
+                    Reason::OverrideActive(Override::Drain),
+                )
+            }
+            Some(target) => {
+                info!(
+                    "Drain to {target}% complete. Normal limits ({low}-{high}%) are back in force"
+                );
+                drain::clear(&state::state_dir())?;
+                draining = None;
+                bus.publish(Event::OverrideSet {
+                    behaviour: None,
+                    reason: Override::Drain,
+                });
+                policy
+            }
+            None => {
+                if draining.take().is_some() {
+                    info!("Drain request was cancelled. Normal limits ({low}-{high}%) are back in force");
+                    bus.publish(Event::OverrideSet {
+                        behaviour: None,
+                        reason: Override::Drain,
+                    });
+                }
+                policy
+            }
+        };
 
-        debug!("Battery capacity {cap}, behaviour {be}");
-        if be != be_new {
-            info!("Setting new charge behaviour: {be_new}. Old was {be}. battery at {cap}% . ");
-            set_behaviour(be_new)?;
+        let travel_on = travel::active(&state::state_dir());
+        let (be_new, reason) = match (travel_on, reason) {
+            (true, r) if r != Reason::OverrideActive(Override::Drain) => {
+                if !traveling {
+                    info!("Travel mode on, allowing a full charge");
+                    bus.publish(Event::OverrideSet {
+                        behaviour: Some(ChargeBehaviour::Auto),
+                        reason: Override::Travel,
+                    });
+                    traveling = true;
+                    trip = Trip::default();
+                }
+                match trip.observe(cap, ac_online) {
+                    Some(used) => {
+                        info!("Used {used}% on battery, travel mode off. Normal limits ({low}-{high}%) are back in force");
+                        travel::disable(&state::state_dir())?;
+                        traveling = false;
+                        bus.publish(Event::OverrideSet {
+                            behaviour: None,
+                            reason: Override::Travel,
+                        });
+                        (be_new, reason)
+                    }
+                    None => (
+                        ChargeBehaviour::Auto,
+                        Reason::OverrideActive(Override::Travel),
+                    ),
+                }
+            }
+            (false, _) if traveling => {
+                info!(
+                    "Travel mode was turned off. Normal limits ({low}-{high}%) are back in force"
+                );
+                traveling = false;
+                bus.publish(Event::OverrideSet {
+                    behaviour: None,
+                    reason: Override::Travel,
+                });
+                (be_new, reason)
+            }
+            _ => (be_new, reason),
+        };
+
+        let full = match full::pending(&state::state_dir()) {
+            Ok(f) => f,
+            Err(e) => {
+                warn!("Could not read full charge request: {e}");
+                bus.publish(Event::Error(format!(
+                    "Could not read full charge request: {e}"
+                )));
+                None
+            }
+        };
+        let (be_new, reason) = match (full, reason) {
+            (Some(deadline), r) if r != Reason::OverrideActive(Override::Drain) => {
+                let done = if snap.status.as_deref() == Some("Full") || cap >= 100 {
+                    Some("complete")
+                } else if clock::now() >= deadline {
+                    Some("timed out")
+                } else {
+                    None
+                };
+                match done {
+                    Some(how) => {
+                        info!("Full charge {how} at {cap}%. Normal limits ({low}-{high}%) are back in force");
+                        full::clear(&state::state_dir())?;
+                        if full_charging {
+                            bus.publish(Event::OverrideSet {
+                                behaviour: None,
+                                reason: Override::FullCharge,
+                            });
+                        }
+                        full_charging = false;
+                        (be_new, reason)
+                    }
+                    None => {
+                        if !full_charging {
+                            info!("Charging to full once");
+                            bus.publish(Event::OverrideSet {
+                                behaviour: Some(ChargeBehaviour::Auto),
+                                reason: Override::FullCharge,
+                            });
+                            full_charging = true;
+                        }
+                        (
+                            ChargeBehaviour::Auto,
+                            Reason::OverrideActive(Override::FullCharge),
+                        )
+                    }
+                }
+            }
+            (_, r) if full_charging && r != Reason::OverrideActive(Override::FullCharge) => {
+                info!("Full charge was cancelled. Normal limits ({low}-{high}%) are back in force");
+                full_charging = false;
+                bus.publish(Event::OverrideSet {
+                    behaviour: None,
+                    reason: Override::FullCharge,
+                });
+                (be_new, reason)
+            }
+            _ => (be_new, reason),
+        };
+
+        if let Reason::OverrideActive(Override::Drain | Override::Travel | Override::FullCharge) =
+            reason
+        {
+            stack.push(Priority::Manual, be_new, reason);
+        }
+
+        if let Some(f) = full_by.as_mut() {
+            let now = clock::now();
+            f.rate.observe(
+                now,
+                cap,
+                ac_online == Some(true) && snap.status.as_deref() == Some("Charging"),
+            );
+            let due =
+                stack.priority() < Priority::Manual && f.step(now, clock::utc_offset(now), cap);
+            if due != charging_by {
+                charging_by = due;
+                if due {
+                    info!("Charging to full by the scheduled time");
+                } else {
+                    info!("No longer charging for the scheduled time. Normal limits ({low}-{high}%) are back in force");
+                }
+                bus.publish(Event::OverrideSet {
+                    behaviour: due.then_some(ChargeBehaviour::Auto),
+                    reason: Override::FullBy,
+                });
+            }
+            if due {
+                stack.push(
+                    Priority::Schedule,
+                    ChargeBehaviour::Auto,
+                    Reason::ScheduleWindow,
+                );
+            }
         }
 
-        sleep(Duration::from_secs(60));
+        match calibration.as_mut() {
+            Some((c, every, floor)) if stack.priority() < Priority::Schedule => {
+                let phase = c.phase;
+                let full = snap.status.as_deref() == Some("Full") || cap >= 100;
+                let b = c.step(clock::now(), cap, full, *every, *floor);
+                if c.phase != phase {
+                    match (phase, c.phase) {
+                        (_, Some(Phase::Charging)) => {
+                            info!("Starting battery calibration, charging to full")
+                        }
+                        (_, Some(Phase::Discharging)) => {
+                            info!("Calibration: battery is full, discharging to {floor}%")
+                        }
+                        (Some(Phase::Charging), None) => warn!(
+                            "Calibration: battery wasn't full after {} hours, giving up on this cycle. \
+                             Normal limits ({low}-{high}%) are back in force",
+                            calibration::CHARGE_TIMEOUT / 3600
+                        ),
+                        (_, None) => info!(
+                            "Calibration complete. Normal limits ({low}-{high}%) are back in force"
+                        ),
+                    }
+                    bus.publish(Event::OverrideSet {
+                        behaviour: b,
+                        reason: Override::Calibration,
+                    });
+                    if let Err(e) = c.save(&state::state_dir()) {
+                        warn!("Could not save calibration state: {e}");
+                    }
+                }
+                if let Some(b) = b {
+                    stack.push(
+                        Priority::Schedule,
+                        b,
+                        Reason::OverrideActive(Override::Calibration),
+                    );
+                }
+            }
+            Some((c, ..)) => {
+                let saved = if c.postpone() {
+                    c.save(&state::state_dir())
+                } else {
+                    Ok(())
+                };
+                if let Err(e) = saved {
+                    warn!("Could not save calibration state: {e}");
+                }
+            }
+            _ => {}
+        }
+
+        let now = clock::now();
+        let local = now as i64 + clock::utc_offset(now);
+        let within = maintenance.iter().any(|w| w.contains(local));
+        if within != maintaining {
+            maintaining = within;
+            if within {
+                info!("Maintenance window started, charging allowed and not force-discharging");
+            } else {
+                info!(
+                    "Maintenance window is over. Normal limits ({low}-{high}%) are back in force"
+                );
+            }
+            bus.publish(Event::OverrideSet {
+                behaviour: within.then_some(ChargeBehaviour::Auto),
+                reason: Override::Maintenance,
+            });
+        }
+        if within {
+            stack.push(
+                Priority::Schedule,
+                ChargeBehaviour::Auto,
+                Reason::OverrideActive(Override::Maintenance),
+            );
+        }
+
+        let charger_watts = config
+            .min_charger_watts
+            .and_then(|min| charger::watts().map(|w| (w, min)));
+        let weak = charger_watts.is_some_and(|(w, min)| w < f64::from(min));
+        if weak != weak_charger {
+            if let Some((w, min)) = charger_watts.filter(|_| weak) {
+                info!("Charger provides {w:.0}W, below {min}W. Not force-discharging");
+            } else {
+                info!("Charger is no longer below the minimum wattage");
+            }
+            weak_charger = weak;
+        }
+        if weak && stack.decide().0 == ChargeBehaviour::ForceDischarge {
+            stack.push(
+                Priority::Failsafe,
+                ChargeBehaviour::InhibitCharge,
+                Reason::Failsafe(Failsafe::WeakCharger),
+            );
+        }
+
+        if discharge.observe(Instant::now(), cap, be, stack.decide().0, ac_online) {
+            stack.push(
+                Priority::Failsafe,
+                ChargeBehaviour::InhibitCharge,
+                Reason::Failsafe(Failsafe::DischargeIneffective),
+            );
+        }
+
+        if let Some(b) = external.observe(Instant::now(), be, ac_steady && !reassert) {
+            stack.push(
+                Priority::Manual,
+                b,
+                Reason::OverrideActive(Override::External),
+            );
+        }
+
+        debug!(
+            "Battery capacity {cap}, behaviour {be}, status {}, temp {}, power {}",
+            snap.status.as_deref().unwrap_or("-"),
+            snap.temp
+                .map_or("-".to_string(), |t| format!("{:.1}°C", f64::from(t) / 10.0)),
+            snap.power_now.map_or("-".to_string(), |p| format!(
+                "{:.2}W",
+                p as f64 / 1_000_000.0
+            )),
+        );
+        match (first, &stance) {
+            (true, StartupStance::Observe) => {
+                let (be_new, reason) = stack.decide();
+                info!("Observing first interval, would set {be_new}. battery at {cap}% .");
+                stack.push(Priority::Manual, be, reason);
+            }
+            (true, StartupStance::Start(b)) => stack.push(Priority::Manual, *b, Reason::Startup),
+            _ => {}
+        }
+        if within && stack.decide().0 == ChargeBehaviour::ForceDischarge {
+            stack.push(
+                Priority::Limit,
+                ChargeBehaviour::Auto,
+                Reason::OverrideActive(Override::Maintenance),
+            );
+        }
+        let hot = thermal.as_mut().is_some_and(|t| t.observe(snap.temp));
+        if hot && stack.decide().0 == ChargeBehaviour::Auto {
+            stack.push(
+                Priority::Limit,
+                ChargeBehaviour::InhibitCharge,
+                Reason::ThermalLimit,
+            );
+        }
+        if let Some(b) = hibernate_floor.and_then(|f| floor::apply(cap, stack.decide().0, f)) {
+            stack.push(
+                Priority::Limit,
+                b,
+                Reason::Failsafe(Failsafe::HibernateFloor),
+            );
+        }
+        let (be_new, reason) = stack.decide();
+        trace!(
+            capacity = cap, behaviour:% = be, chosen:% = be_new, reason:% = reason;
+            "decision capacity={cap} ac_online={} behaviour={be} drain={} first={first} monitor={monitor} chosen={be_new} reason=\"{reason}\" kind={}",
+            ac_online.map_or("-".to_string(), |o| o.to_string()),
+            drain.map_or("-".to_string(), |t| t.to_string()),
+            reason.kind(),
+        );
+        if first {
+            readiness::notify_ready()?;
+            if config.trim_heap {
+                // Most allocations are done by now, give back what startup used.
+                memory::trim();
+            }
+        }
+        first = false;
+        let mut current = be;
+        if ac_online == Some(false) {
+            // Charge behaviour only matters on AC, it's set on plug-in.
+            if be != be_new {
+                debug!("On battery, leaving charge behaviour at {be} instead of {be_new}");
+            }
+        } else if monitor {
+            if be != be_new && recommended != Some(be_new) {
+                info!(
+                    capacity = cap, old_behaviour:% = be, new_behaviour:% = be_new, reason:% = reason;
+                    "Monitor mode, would set charge behaviour: {be_new}. Current is {be}. battery at {cap}% ."
+                );
+                bus.publish(Event::TransitionRecommended {
+                    old: be,
+                    new: be_new,
+                    capacity: cap,
+                    reason,
+                });
+            }
+            recommended = Some(be_new);
+        } else {
+            backoff.set_context(backoff::Context {
+                ac_online,
+                adapter_watts: charger::watts().map(|w| w.round() as u32),
+            });
+            let chosen = backoff.choose(be_new);
+            if chosen != be_new {
+                debug!("{be_new} was rejected in this state, using {chosen} instead");
+            }
+            if be != chosen || reassert {
+                if be == chosen {
+                    info!(
+                        capacity = cap, behaviour:% = chosen, reason:% = reason;
+                        "Re-asserting charge behaviour {chosen}. battery at {cap}% ."
+                    );
+                } else {
+                    info!(
+                        capacity = cap, old_behaviour:% = be, new_behaviour:% = chosen, reason:% = reason;
+                        "Setting new charge behaviour: {chosen}. Old was {be}. battery at {cap}% . "
+                    );
+                }
+                match set_behaviour(chosen) {
+                    Ok(()) => {
+                        backoff.record_success(chosen);
+                        if be != chosen {
+                            last_transition = Some(clock::format_timestamp(clock::now()));
+                        }
+                        current = chosen;
+                        bus.publish(Event::TransitionApplied {
+                            old: be,
+                            new: chosen,
+                            capacity: cap,
+                            reason,
+                        });
+                    }
+                    Err(e) => {
+                        warn!("Could not set charge behaviour {chosen}: {e}");
+                        bus.publish(Event::Error(format!(
+                            "Could not set charge behaviour {chosen}: {e}"
+                        )));
+                        if backoff.record_failure(chosen) {
+                            warn!("{chosen} keeps being rejected, falling back until AC state or adapter changes");
+                        }
+                    }
+                }
+            }
+        }
+
+        external.left_at(current);
+        history.observe(clock::now(), cap, current, ac_online);
+
+        let status = Status {
+            updated: clock::format_timestamp(clock::now()),
+            capacity: cap,
+            behaviour: current.to_string(),
+            ac_online,
+            low_threshold: low,
+            high_threshold: high,
+            rss_kib: memory::rss_kib(),
+            profile: using_profile.clone(),
+            last_transition: last_transition.clone(),
+            stack: stack
+                .layers()
+                .iter()
+                .map(|l| StackEntry {
+                    priority: l.priority.to_string(),
+                    behaviour: l.behaviour.to_string(),
+                    reason: l.reason.to_string(),
+                })
+                .collect(),
+            firmware_limit: firmware_limit.get(),
+            health: health.get().map(|f| status::Health {
+                percent: f.current,
+                per_month: f.per_month,
+                replace_by: f.replace_on.map(clock::format_day),
+            }),
+            forecast: history.forecast(cap, current, ac_online, high, until),
+            // Storage mode works through the thresholds, it isn't a layer.
+            active_override: stack
+                .active_override()
+                .or(storing.then_some(Override::Storage))
+                .map(|o| o.to_string()),
+        };
+        if let Err(e) = status::write(&state::state_dir(), &status) {
+            warn!("Could not write status: {e}");
+        }
+        if let Some(m) = mqtt.as_mut() {
+            m.publish_status(&status);
+        }
+
+        if let Err(e) = readiness::sd_notify(&format!(
+            "STATUS={current} at {cap}% ({reason}), thresholds {low}-{high}%"
+        )) {
+            debug!("Could not notify systemd: {e}");
+        }
+        match pause(Duration::from_secs(config.interval), watchdog, &wake) {
+            Some(Wake::Stop) => break,
+            // What prepare-sleep set before suspending isn't a change by hand.
+            w => reassert = matches!(w, Some(Wake::Reregistered | Wake::Resume)),
+        }
+    }
+
+    if let Err(e) = readiness::sd_notify("STOPPING=1") {
+        debug!("Could not notify systemd: {e}");
+    }
+    if let Some(m) = &mqtt {
+        m.stop();
+    }
+    match config.on_exit.behaviour(original) {
+        Some(b) if !monitor => apply_behaviour(&mut bus, b, Reason::Exit)?,
+        _ => info!("Stopping, leaving charge behaviour as it is"),
+    }
+    Ok(())
+}
+
+/// Wait until `path` exists, failing after `timeout`.
+fn wait_for(path: &Path, timeout: Duration) -> Result<(), anyhow::Error> {
+    if path.exists() {
+        return Ok(());
+    }
+    info!("Waiting up to {timeout:?} for {} to appear", path.display());
+    let start = Instant::now();
+    while !path.exists() {
+        if start.elapsed() >= timeout {
+            return Err(anyhow!(
+                "{} did not appear within {timeout:?} (wait_for_timeout)",
+                path.display()
+            ));
+        }
+        // Not ready yet, keep systemd from giving up on the start first.
+        if let Err(e) = readiness::sd_notify("EXTEND_TIMEOUT_USEC=5000000") {
+            debug!("Could not notify systemd: {e}");
+        }
+        sleep(Duration::from_secs(1).min(timeout.saturating_sub(start.elapsed())));
+    }
+    Ok(())
+}
+
+/// Wait for `interval` or until a power_supply uevent arrives, keeping the
+/// systemd watchdog fed if it's enabled. Returns what woke it early, if anything.
+fn pause(interval: Duration, watchdog: Option<Duration>, wake: &Receiver<Wake>) -> Option<Wake> {
+    let start = Instant::now();
+    loop {
+        if watchdog.is_some() {
+            if let Err(e) = readiness::sd_notify("WATCHDOG=1") {
+                debug!("Could not notify systemd watchdog: {e}");
+            }
+        }
+        let left = interval.saturating_sub(start.elapsed());
+        if left.is_zero() {
+            return None;
+        }
+        let chunk = watchdog.map_or(left, |w| left.min(w / 2));
+        match wake.recv_timeout(chunk) {
+            Ok(Wake::Uevent) => {
+                // Events come in bursts, let them settle and handle them at once.
+                sleep(UEVENT_SETTLE);
+                debug!("Woken by a power_supply uevent");
+                return Some(drain_wakes(wake, Wake::Uevent));
+            }
+            Ok(Wake::Reregistered) => {
+                // Give the device time to finish registering all attributes.
+                sleep(UEVENT_SETTLE);
+                info!("A power_supply device was re-registered, re-asserting the charge behaviour");
+                return Some(drain_wakes(wake, Wake::Reregistered));
+            }
+            Ok(Wake::Resume) => {
+                // The command line signals the same way after a request.
+                info!(
+                    "Resumed from suspend or woken by a command, re-asserting the charge behaviour"
+                );
+                return Some(drain_wakes(wake, Wake::Resume));
+            }
+            Ok(Wake::Request) => {
+                debug!("Woken by a request, evaluating now");
+                return Some(drain_wakes(wake, Wake::Request));
+            }
+            Ok(Wake::Stop) => return Some(Wake::Stop),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => sleep(chunk),
+        }
     }
 }
 
-fn get_capacity() -> Result<i8, anyhow::Error> {
-    let s = fs::read_to_string("/sys/class/power_supply/macsmc-battery/capacity")?;
-    let cap = s.trim().parse::<i8>()?;
-    Ok(cap)
+/// Drain the wake-ups that arrived after `first`, returning the one that
+/// matters most: stopping, then re-asserting the behaviour, then resuming.
+fn drain_wakes(wake: &Receiver<Wake>, first: Wake) -> Wake {
+    let mut most = first;
+    while let Ok(w) = wake.try_recv() {
+        most = match (most, w) {
+            (Wake::Stop, _) | (_, Wake::Stop) => Wake::Stop,
+            (Wake::Reregistered, _) | (_, Wake::Reregistered) => Wake::Reregistered,
+            (Wake::Resume, _) | (_, Wake::Resume) => Wake::Resume,
+            (m, _) => m,
+        };
+    }
+    most
 }
 
-fn calc_behaviour(cap: i8, cb: &ChargeBehaviour) -> ChargeBehaviour {
+/// Re-read the config file and apply the command line overrides on top.
+/// Settings that are only used on startup, such as sysfs paths and the
+/// integrations, are kept and the ones that changed are logged.
+fn reload_config(cli: &Cli, current: &Config) -> Result<Config, anyhow::Error> {
+    let mut new = Config::load(&cli.config)?.unwrap_or_default();
+    cli.apply(&mut new)?;
+    let mut restart = Vec::new();
+    macro_rules! keep {
+        ($($key:ident),*) => {$(
+            if new.$key != current.$key {
+                restart.push(stringify!($key));
+                new.$key = current.$key.clone();
+            }
+        )*};
+    }
+    keep!(
+        battery,
+        ac,
+        log,
+        monitor,
+        uevents,
+        inhibit_sleep,
+        influx,
+        http,
+        mqtt,
+        instance,
+        replace_at,
+        trim_heap
+    );
+    if !restart.is_empty() {
+        warn!(
+            "Changes to {} only take effect after a restart",
+            restart.join(", ")
+        );
+    }
+    Ok(new)
+}
+
+fn full_by_schedule(config: &Config) -> Result<Option<FullBy>, anyhow::Error> {
+    config
+        .full_by
+        .as_ref()
+        .map(|f| Schedule::parse(&f.time, &f.days).map(FullBy::new))
+        .transpose()
+}
+
+fn maintenance_windows(config: &Config) -> Result<Vec<Window>, anyhow::Error> {
+    config
+        .maintenance
+        .iter()
+        .map(|m| Window::parse(&m.start, &m.end, &m.days))
+        .collect()
+}
+
+/// The calibration state, seconds between cycles and floor, if calibrating.
+/// `running` is the state kept from before a reload, loaded if `None`.
+fn calibration_cycle(
+    config: &Config,
+    running: Option<Calibration>,
+) -> Result<Option<(Calibration, u64, i8)>, anyhow::Error> {
+    let Some(weeks) = config.calibration_weeks else {
+        return Ok(None);
+    };
+    let c = match running {
+        Some(c) => c,
+        None => Calibration::load(&state::state_dir(), clock::now())?,
+    };
+    Ok(Some((
+        c,
+        u64::from(weeks) * 7 * 24 * 60 * 60,
+        config.calibration_floor,
+    )))
+}
+
+fn hibernate_floor_level(config: &Config) -> Option<i8> {
+    config.hibernate_margin.map(|margin| {
+        let level = config
+            .hibernate_level
+            .unwrap_or_else(|| floor::upower_action_level(Path::new(floor::UPOWER_CONF)));
+        let f = level.saturating_add(margin).min(100);
+        info!("Never letting the battery go below {f}% (hibernate level {level}% + {margin}%)");
+        f
+    })
+}
+
+fn calc_behaviour(cap: i8, cb: &ChargeBehaviour, low: i8, high: i8) -> ChargeBehaviour {
+    calc_discharging(cap, cb, low, high, high, high)
+}
+
+/// Like [`calc_behaviour`], but force-discharging only above `above`, and
+/// then all the way down to `until`.
+fn calc_discharging(
+    cap: i8,
+    cb: &ChargeBehaviour,
+    low: i8,
+    high: i8,
+    above: i8,
+    until: i8,
+) -> ChargeBehaviour {
     match (cap, cb) {
         // This should ensure that if we're > max we discharge until max and then inhibit,
         // and if we're < low then we'll charge all the way to max.
-        (c, _) if c > HIGH_THRESHOLD => ChargeBehaviour::ForceDischarge,
-        (c, _) if c < LOW_THRESHOLD => ChargeBehaviour::Auto,
-        (c, ChargeBehaviour::Auto) if c < HIGH_THRESHOLD => ChargeBehaviour::Auto,
-        (c, ChargeBehaviour::ForceDischarge) if c < HIGH_THRESHOLD => {
-            ChargeBehaviour::InhibitCharge
-        }
+        (c, _) if c > above => ChargeBehaviour::ForceDischarge,
+        (c, ChargeBehaviour::ForceDischarge) if c > until => ChargeBehaviour::ForceDischarge,
+        (c, _) if c < low => ChargeBehaviour::Auto,
+        (c, ChargeBehaviour::Auto) if c < high => ChargeBehaviour::Auto,
         (_, _) => ChargeBehaviour::InhibitCharge,
     }
 }
 
+/// Human readable description of which policy branch applies at `cap`.
+fn policy_reason(cap: i8, low: i8, high: i8) -> Reason {
+    match cap {
+        c if c > high => Reason::AboveHigh,
+        c if c < low => Reason::BelowLow,
+        _ => Reason::WithinThresholds,
+    }
+}
+
+/// Whether `e` is a sysfs operation that timed out.
+fn timed_out(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::TimedOut)
+}
+
 fn get_behaviour() -> Result<ChargeBehaviour, anyhow::Error> {
-    let s = fs::read_to_string("/sys/class/power_supply/macsmc-battery/charge_behaviour")?;
+    let s = sysfs::read(sysfs::battery("charge_behaviour"))?;
     let b = s.as_str().parse::<ChargeBehaviour>()?;
     Ok(b)
 }
 
+/// Set `b` outside the control loop, logged and published on `bus` like
+/// the loop's own writes.
+fn apply_behaviour(
+    bus: &mut EventBus,
+    b: ChargeBehaviour,
+    reason: Reason,
+) -> Result<(), anyhow::Error> {
+    let snap = match Snapshot::read() {
+        Ok(snap) => snap,
+        Err(e) => {
+            // Still worth trying, charge_behaviour may not be the one stuck.
+            warn!(
+                "Could not read the battery, setting charge behaviour {b} ({reason}) anyway: {e:#}"
+            );
+            set_behaviour(b)?;
+            bus.publish(Event::Error(format!("Could not read the battery: {e:#}")));
+            return Ok(());
+        }
+    };
+    let (cap, be) = (snap.capacity, snap.behaviour);
+    info!(
+        capacity = cap, old_behaviour:% = be, new_behaviour:% = b, reason:% = reason;
+        "Setting charge behaviour {b} ({reason}). Old was {be}. battery at {cap}% ."
+    );
+    set_behaviour(b)?;
+    bus.publish(Event::TransitionApplied {
+        old: be,
+        new: b,
+        capacity: cap,
+        reason,
+    });
+    Ok(())
+}
+
 fn set_behaviour(b: ChargeBehaviour) -> Result<(), anyhow::Error> {
-    fs::write(
-        "/sys/class/power_supply/macsmc-battery/charge_behaviour",
-        b.to_string(),
-    )?;
+    sysfs::write(sysfs::battery("charge_behaviour"), &b.to_string())?;
     Ok(())
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 enum ChargeBehaviour {
     Auto,
     ForceDischarge,
@@ -116,35 +1504,133 @@ impl Display for ChargeBehaviour {
     }
 }
 
+/// What to do on the first evaluation after starting up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+enum StartupStance {
+    /// Write the computed behaviour immediately.
+    Enforce,
+    /// Don't write anything until one full interval has passed.
+    Observe,
+    /// Write the given behaviour first, then follow the policy.
+    Start(ChargeBehaviour),
+}
+
+impl FromStr for StartupStance {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "enforce" => Ok(Self::Enforce),
+            "observe" => Ok(Self::Observe),
+            s => s
+                .parse::<ChargeBehaviour>()
+                .map(Self::Start)
+                .map_err(|_| anyhow!("Unknown startup stance {s}!")),
+        }
+    }
+}
+
+impl TryFrom<String> for StartupStance {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for StartupStance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartupStance::Enforce => write!(f, "enforce"),
+            StartupStance::Observe => write!(f, "observe"),
+            StartupStance::Start(b) => write!(f, "start from {b}"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{calc_behaviour, ChargeBehaviour, HIGH_THRESHOLD, LOW_THRESHOLD};
+    use std::path::Path;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use crate::wake::Wake;
+    use crate::{
+        calc_behaviour, calc_discharging, drain_wakes, wait_for, ChargeBehaviour, StartupStance,
+        HIGH_THRESHOLD, LOW_THRESHOLD,
+    };
+
+    #[test]
+    fn discharge_band_goes_past_high_threshold() {
+        let (fd, auto, inhibit) = (
+            ChargeBehaviour::ForceDischarge,
+            ChargeBehaviour::Auto,
+            ChargeBehaviour::InhibitCharge,
+        );
+        assert_eq!(inhibit, calc_discharging(90, &auto, 70, 80, 95, 75));
+        assert_eq!(inhibit, calc_discharging(90, &inhibit, 70, 80, 95, 75));
+        assert_eq!(fd, calc_discharging(96, &inhibit, 70, 80, 95, 75));
+        assert_eq!(fd, calc_discharging(80, &fd, 70, 80, 95, 75));
+        assert_eq!(inhibit, calc_discharging(75, &fd, 70, 80, 95, 75));
+        assert_eq!(auto, calc_discharging(69, &inhibit, 70, 80, 95, 75));
+    }
 
     #[test]
     fn calculate_from_force_discharge_behaviour() {
         assert_eq!(
             ChargeBehaviour::ForceDischarge,
-            calc_behaviour(HIGH_THRESHOLD + 1, &ChargeBehaviour::ForceDischarge)
+            calc_behaviour(
+                HIGH_THRESHOLD + 1,
+                &ChargeBehaviour::ForceDischarge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(HIGH_THRESHOLD, &ChargeBehaviour::ForceDischarge)
+            calc_behaviour(
+                HIGH_THRESHOLD,
+                &ChargeBehaviour::ForceDischarge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(HIGH_THRESHOLD - 1, &ChargeBehaviour::ForceDischarge)
+            calc_behaviour(
+                HIGH_THRESHOLD - 1,
+                &ChargeBehaviour::ForceDischarge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(LOW_THRESHOLD + 1, &ChargeBehaviour::ForceDischarge)
+            calc_behaviour(
+                LOW_THRESHOLD + 1,
+                &ChargeBehaviour::ForceDischarge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(LOW_THRESHOLD, &ChargeBehaviour::ForceDischarge)
+            calc_behaviour(
+                LOW_THRESHOLD,
+                &ChargeBehaviour::ForceDischarge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::Auto,
-            calc_behaviour(LOW_THRESHOLD - 1, &ChargeBehaviour::ForceDischarge)
+            calc_behaviour(
+                LOW_THRESHOLD - 1,
+                &ChargeBehaviour::ForceDischarge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
     }
 
@@ -152,27 +1638,57 @@ mod tests {
     fn calculate_from_inhibit_behaviour() {
         assert_eq!(
             ChargeBehaviour::ForceDischarge,
-            calc_behaviour(HIGH_THRESHOLD + 1, &ChargeBehaviour::InhibitCharge)
+            calc_behaviour(
+                HIGH_THRESHOLD + 1,
+                &ChargeBehaviour::InhibitCharge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(HIGH_THRESHOLD, &ChargeBehaviour::InhibitCharge)
+            calc_behaviour(
+                HIGH_THRESHOLD,
+                &ChargeBehaviour::InhibitCharge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(HIGH_THRESHOLD - 1, &ChargeBehaviour::InhibitCharge)
+            calc_behaviour(
+                HIGH_THRESHOLD - 1,
+                &ChargeBehaviour::InhibitCharge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(LOW_THRESHOLD + 1, &ChargeBehaviour::InhibitCharge)
+            calc_behaviour(
+                LOW_THRESHOLD + 1,
+                &ChargeBehaviour::InhibitCharge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(LOW_THRESHOLD, &ChargeBehaviour::InhibitCharge)
+            calc_behaviour(
+                LOW_THRESHOLD,
+                &ChargeBehaviour::InhibitCharge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::Auto,
-            calc_behaviour(LOW_THRESHOLD - 1, &ChargeBehaviour::InhibitCharge)
+            calc_behaviour(
+                LOW_THRESHOLD - 1,
+                &ChargeBehaviour::InhibitCharge,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
     }
 
@@ -180,27 +1696,57 @@ mod tests {
     fn calculate_from_auto_behaviour() {
         assert_eq!(
             ChargeBehaviour::ForceDischarge,
-            calc_behaviour(HIGH_THRESHOLD + 1, &ChargeBehaviour::Auto)
+            calc_behaviour(
+                HIGH_THRESHOLD + 1,
+                &ChargeBehaviour::Auto,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::InhibitCharge,
-            calc_behaviour(HIGH_THRESHOLD, &ChargeBehaviour::Auto)
+            calc_behaviour(
+                HIGH_THRESHOLD,
+                &ChargeBehaviour::Auto,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::Auto,
-            calc_behaviour(HIGH_THRESHOLD - 1, &ChargeBehaviour::Auto)
+            calc_behaviour(
+                HIGH_THRESHOLD - 1,
+                &ChargeBehaviour::Auto,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::Auto,
-            calc_behaviour(LOW_THRESHOLD + 1, &ChargeBehaviour::Auto)
+            calc_behaviour(
+                LOW_THRESHOLD + 1,
+                &ChargeBehaviour::Auto,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::Auto,
-            calc_behaviour(LOW_THRESHOLD, &ChargeBehaviour::Auto)
+            calc_behaviour(
+                LOW_THRESHOLD,
+                &ChargeBehaviour::Auto,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
         assert_eq!(
             ChargeBehaviour::Auto,
-            calc_behaviour(LOW_THRESHOLD - 1, &ChargeBehaviour::Auto)
+            calc_behaviour(
+                LOW_THRESHOLD - 1,
+                &ChargeBehaviour::Auto,
+                LOW_THRESHOLD,
+                HIGH_THRESHOLD
+            )
         );
     }
 
@@ -217,4 +1763,32 @@ mod tests {
         let p = s.parse::<ChargeBehaviour>().unwrap();
         assert_eq!(s, p.to_string());
     }
+
+    #[test]
+    fn parse_startup_stance() {
+        assert_eq!(StartupStance::Enforce, "enforce".parse().unwrap());
+        assert_eq!(StartupStance::Observe, "observe".parse().unwrap());
+        assert_eq!(
+            StartupStance::Start(ChargeBehaviour::InhibitCharge),
+            "inhibit-charge".parse().unwrap()
+        );
+        assert!("sometimes".parse::<StartupStance>().is_err());
+    }
+
+    #[test]
+    fn wait_for_gives_up() {
+        assert!(wait_for(Path::new("/dev/null"), Duration::ZERO).is_ok());
+        let err = wait_for(Path::new("/nonexistent"), Duration::from_millis(10)).unwrap_err();
+        assert!(err.to_string().contains("did not appear"));
+    }
+
+    #[test]
+    fn resume_outranks_other_wakes() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(Wake::Resume).unwrap();
+        tx.send(Wake::Request).unwrap();
+        assert_eq!(Wake::Resume, drain_wakes(&rx, Wake::Uevent));
+        tx.send(Wake::Resume).unwrap();
+        assert_eq!(Wake::Reregistered, drain_wakes(&rx, Wake::Reregistered));
+    }
 }
@@ -0,0 +1,59 @@
+use crate::schedule::{parse_days, parse_time, weekday};
+
+/// A weekly window, e.g. for backups or CI jobs, during which the battery is
+/// left to charge and never force-discharged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    /// Seconds after local midnight.
+    start: i64,
+    end: i64,
+    /// Days the window starts on, Monday first.
+    days: [bool; 7],
+}
+
+impl Window {
+    /// Parse `HH:MM` start and end times and day names (`mon`..`sun`), every
+    /// day if empty. A window ending before it starts runs past midnight.
+    pub fn parse(start: &str, end: &str, days: &[String]) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            days: parse_days(days)?,
+        })
+    }
+
+    /// Whether `local` (local seconds since the epoch) is within the window.
+    pub fn contains(&self, local: i64) -> bool {
+        let (day, time) = (local.div_euclid(86400), local.rem_euclid(86400));
+        if self.start <= self.end {
+            self.days[weekday(day)] && (self.start..self.end).contains(&time)
+        } else {
+            (self.days[weekday(day)] && time >= self.start)
+                || (self.days[weekday(day - 1)] && time < self.end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::maintenance::Window;
+
+    // 2023-04-03, a Monday, at 00:00.
+    const MONDAY: i64 = 19450 * 86400;
+    const HOUR: i64 = 3600;
+
+    #[test]
+    fn windows_by_day_and_past_midnight() {
+        let w = Window::parse("02:00", "04:00", &["mon".to_string()]).unwrap();
+        assert!(!w.contains(MONDAY + HOUR));
+        assert!(w.contains(MONDAY + 2 * HOUR));
+        assert!(!w.contains(MONDAY + 4 * HOUR));
+        assert!(!w.contains(MONDAY + 24 * HOUR + 3 * HOUR));
+
+        let w = Window::parse("23:00", "01:00", &["sun".to_string()]).unwrap();
+        assert!(w.contains(MONDAY - HOUR / 2));
+        assert!(w.contains(MONDAY + HOUR / 2));
+        assert!(!w.contains(MONDAY + 23 * HOUR));
+        assert!(Window::parse("02:00", "25:00", &[]).is_err());
+    }
+}
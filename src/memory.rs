@@ -0,0 +1,40 @@
+use std::fs;
+
+/// Resident set size of this process in KiB, from /proc/self/status.
+pub fn rss_kib() -> Option<u64> {
+    parse_rss(&fs::read_to_string("/proc/self/status").ok()?)
+}
+
+fn parse_rss(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Return freed heap memory to the system, where the allocator supports it.
+pub fn trim() {
+    // SAFETY: malloc_trim has no preconditions.
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    unsafe {
+        libc::malloc_trim(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::parse_rss;
+
+    #[test]
+    fn parse_proc_status() {
+        assert_eq!(
+            Some(2048),
+            parse_rss("Name:\tmacsmc-charged\nVmPeak:\t  10000 kB\nVmRSS:\t    2048 kB\n")
+        );
+        assert_eq!(None, parse_rss("Name:\tmacsmc-charged\n"));
+    }
+}
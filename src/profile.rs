@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::config::Config;
+use crate::state::RequestFile;
+
+const FILE: RequestFile = RequestFile::new("profile");
+
+/// Make `name` the active profile, checking `config` defines it.
+pub fn select(dir: &Path, config: &Config, name: &str) -> Result<(), anyhow::Error> {
+    if !config.profiles.contains_key(name) {
+        return Err(anyhow!(
+            "No profile {name:?} in the config, known profiles: {}",
+            config
+                .profiles
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    FILE.write(dir, name)
+}
+
+/// Name of the active profile, if any.
+pub fn active(dir: &Path) -> Result<Option<String>, anyhow::Error> {
+    Ok(FILE.read(dir)?.filter(|s| !s.is_empty()))
+}
+
+pub fn clear(dir: &Path) -> Result<(), anyhow::Error> {
+    FILE.clear(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::config::Config;
+    use crate::profile::select;
+
+    #[test]
+    fn reject_unknown_profile() {
+        let config = Config::parse(
+            r#"
+            [profiles.desk]
+            low_threshold = 60
+            high_threshold = 70
+            "#,
+        )
+        .unwrap();
+        let err = select(Path::new("/nonexistent"), &config, "mobile").unwrap_err();
+        assert!(err.to_string().contains("known profiles: desk"));
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs::File;
+use std::io::Write;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use log::debug;
+
+/// Send `msg` to systemd's notification socket in `$NOTIFY_SOCKET`, as for
+/// `Type=notify` services. Does nothing if unset.
+pub fn sd_notify(msg: &str) -> Result<(), anyhow::Error> {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let addr = match path.to_str().and_then(|p| p.strip_prefix('@')) {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(&path)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(msg.as_bytes(), &addr)?;
+    Ok(())
+}
+
+/// How often systemd expects `WATCHDOG=1`, if `WatchdogSec=` is set for us.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.trim().parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.trim().parse().ok()?;
+    Some(Duration::from_micros(usec))
+}
+
+/// Signal readiness to systemd, and using the s6/dinit protocol. See
+/// [`notify_fd`].
+pub fn notify_ready() -> Result<(), anyhow::Error> {
+    sd_notify("READY=1")?;
+    notify_fd()
+}
+
+/// Signal readiness using the s6/dinit protocol: write a newline to the file
+/// descriptor named in `$MACSMC_READY_FD` and close it. Does nothing if unset.
+///
+/// Must only be called once, as the descriptor is closed afterwards.
+fn notify_fd() -> Result<(), anyhow::Error> {
+    let Ok(fd) = std::env::var("MACSMC_READY_FD") else {
+        return Ok(());
+    };
+    let fd = fd
+        .trim()
+        .parse::<RawFd>()
+        .map_err(|_| anyhow!("Invalid MACSMC_READY_FD {fd}"))?;
+    if fd < 3 {
+        return Err(anyhow!("Refusing to use stdio fd {fd} for readiness"));
+    }
+    // SAFETY: the supervisor hands us this descriptor for exclusive use, and
+    // we only take ownership of it once.
+    let mut f = unsafe { File::from_raw_fd(fd) };
+    f.write_all(b"\n")?;
+    debug!("Notified readiness on fd {fd}");
+    Ok(())
+}
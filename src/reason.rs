@@ -0,0 +1,118 @@
+use std::fmt::Display;
+
+/// Modes that take over from the thresholds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Override {
+    Drain,
+    Travel,
+    FullCharge,
+    /// Charging to be full by the `full_by` time.
+    FullBy,
+    Calibration,
+    Storage,
+    /// A behaviour set outside the daemon, during its grace period.
+    External,
+    /// Within a configured maintenance window.
+    Maintenance,
+}
+
+impl Override {
+    pub fn name(self) -> &'static str {
+        match self {
+            Override::Drain => "drain",
+            Override::Travel => "travel",
+            Override::FullCharge => "full-charge",
+            Override::FullBy => "full-by",
+            Override::Calibration => "calibration",
+            Override::Storage => "storage",
+            Override::External => "external change",
+            Override::Maintenance => "maintenance window",
+        }
+    }
+}
+
+impl Display for Override {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Safety measures that take precedence over what the policy wants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Failsafe {
+    HibernateFloor,
+    WeakCharger,
+    DischargeIneffective,
+}
+
+/// Why a charge behaviour was chosen. Displays as the description used in
+/// logs, [`Reason::kind`] is a stable name for machines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reason {
+    AboveHigh,
+    BelowLow,
+    WithinThresholds,
+    /// The behaviour requested for the first evaluation by `MACSMC_STARTUP`.
+    Startup,
+    /// `sleep_behaviour`, set by `prepare-sleep` before suspending.
+    Sleep,
+    /// The `on_exit` behaviour, set when the daemon stops.
+    Exit,
+    OverrideActive(Override),
+    ScheduleWindow,
+    ThermalLimit,
+    Failsafe(Failsafe),
+}
+
+impl Reason {
+    pub fn kind(self) -> &'static str {
+        match self {
+            Reason::AboveHigh => "above_high",
+            Reason::BelowLow => "below_low",
+            Reason::WithinThresholds => "within_thresholds",
+            Reason::Startup => "startup",
+            Reason::Sleep => "sleep",
+            Reason::Exit => "exit",
+            Reason::OverrideActive(_) => "override_active",
+            Reason::ScheduleWindow => "schedule_window",
+            Reason::ThermalLimit => "thermal_limit",
+            Reason::Failsafe(_) => "failsafe",
+        }
+    }
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Reason::AboveHigh => "above high threshold",
+            Reason::BelowLow => "below low threshold",
+            Reason::WithinThresholds => "within thresholds",
+            Reason::Startup => "startup",
+            Reason::Sleep => "going to sleep",
+            Reason::Exit => "stopping",
+            Reason::OverrideActive(o) => o.name(),
+            Reason::ScheduleWindow => Override::FullBy.name(),
+            Reason::ThermalLimit => "too hot",
+            Reason::Failsafe(Failsafe::HibernateFloor) => "hibernate floor",
+            Reason::Failsafe(Failsafe::WeakCharger) => "weak charger",
+            Reason::Failsafe(Failsafe::DischargeIneffective) => "force-discharge ineffective",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reason::{Failsafe, Override, Reason};
+
+    #[test]
+    fn descriptions_and_kinds() {
+        let r = Reason::OverrideActive(Override::Drain);
+        assert_eq!("drain", r.to_string());
+        assert_eq!("override_active", r.kind());
+        let r = Reason::Failsafe(Failsafe::HibernateFloor);
+        assert_eq!("hibernate floor", r.to_string());
+        assert_eq!("failsafe", r.kind());
+        assert_eq!("full-by", Reason::ScheduleWindow.to_string());
+    }
+}
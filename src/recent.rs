@@ -0,0 +1,76 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::thread;
+
+use log::info;
+use signal_hook::consts::SIGUSR1;
+use signal_hook::iterator::Signals;
+
+use crate::clock;
+use crate::events::{Event, Subscriber};
+
+/// Number of events kept for diagnostics.
+const CAPACITY: usize = 100;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+fn push(buf: &mut VecDeque<String>, entry: String) {
+    if buf.len() == CAPACITY {
+        buf.pop_front();
+    }
+    buf.push_back(entry);
+}
+
+/// The most recent events, oldest first. Empty if the buffer is currently
+/// locked, so this is safe to call from a panic hook.
+pub fn snapshot() -> Vec<String> {
+    RECENT
+        .try_lock()
+        .map(|b| b.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Log the recent events whenever SIGUSR1 is received.
+pub fn dump_on_sigusr1() -> Result<(), anyhow::Error> {
+    let mut signals = Signals::new([SIGUSR1])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            let events = snapshot();
+            info!("Dumping {} recent events", events.len());
+            for e in events {
+                info!("  {e}");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Keeps the last events in a ring buffer for diagnostics.
+pub struct RecentEvents;
+
+impl Subscriber for RecentEvents {
+    fn handle(&mut self, event: &Event) {
+        let entry = format!("{} {event:?}", clock::format_timestamp(clock::now()));
+        if let Ok(mut buf) = RECENT.lock() {
+            push(&mut buf, entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::recent::{push, CAPACITY};
+
+    #[test]
+    fn oldest_events_are_dropped() {
+        let mut buf = VecDeque::new();
+        for i in 0..CAPACITY + 5 {
+            push(&mut buf, i.to_string());
+        }
+        assert_eq!(CAPACITY, buf.len());
+        assert_eq!(Some(&"5".to_string()), buf.front());
+        assert_eq!(Some(&(CAPACITY + 4).to_string()), buf.back());
+    }
+}
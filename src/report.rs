@@ -0,0 +1,261 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::{format_day, parse_day};
+use crate::health::HealthPoint;
+use crate::state;
+use crate::summary::DailySummary;
+use crate::sysfs::Latency;
+
+/// Number of days of daily reports to keep in the reports directory.
+pub const REPORT_RETENTION_DAYS: i64 = 30;
+
+/// Battery health of every day, kept for good since it declines over years.
+const HEALTH_FILE: &str = "health.jsonl";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DailyReport {
+    day: String,
+    min_capacity: Option<i8>,
+    max_capacity: Option<i8>,
+    transitions: u32,
+    ac_time_secs: u64,
+    plug_ins: u32,
+    unplugs: u32,
+    energy_in_uwh: i64,
+    energy_out_uwh: i64,
+    errors: u32,
+    io_latency: Latency,
+    charge_full: Option<i64>,
+    charge_full_design: Option<i64>,
+}
+
+impl From<&DailySummary> for DailyReport {
+    fn from(s: &DailySummary) -> Self {
+        Self {
+            day: format_day(s.day),
+            min_capacity: s.min_capacity,
+            max_capacity: s.max_capacity,
+            transitions: s.transitions,
+            ac_time_secs: s.ac_time.as_secs(),
+            plug_ins: s.plug_ins,
+            unplugs: s.unplugs,
+            energy_in_uwh: s.energy_in,
+            energy_out_uwh: s.energy_out,
+            errors: s.errors,
+            io_latency: s.io_latency.clone(),
+            charge_full: s.charge_full,
+            charge_full_design: s.charge_full_design,
+        }
+    }
+}
+
+impl TryFrom<DailyReport> for DailySummary {
+    type Error = anyhow::Error;
+
+    fn try_from(r: DailyReport) -> Result<Self, Self::Error> {
+        let day = parse_day(&r.day).ok_or_else(|| anyhow!("Invalid day {:?}", r.day))?;
+        let mut s = DailySummary::new(day);
+        s.min_capacity = r.min_capacity;
+        s.max_capacity = r.max_capacity;
+        s.transitions = r.transitions;
+        s.ac_time = Duration::from_secs(r.ac_time_secs);
+        s.plug_ins = r.plug_ins;
+        s.unplugs = r.unplugs;
+        s.energy_in = r.energy_in_uwh;
+        s.energy_out = r.energy_out_uwh;
+        s.errors = r.errors;
+        s.io_latency = r.io_latency;
+        s.charge_full = r.charge_full;
+        s.charge_full_design = r.charge_full_design;
+        Ok(s)
+    }
+}
+
+/// The report written for `day`, if there is one, e.g. the part of today
+/// recorded before the daemon was restarted.
+pub fn load_daily(dir: &Path, day: i64) -> Result<Option<DailySummary>, anyhow::Error> {
+    let s = match fs::read_to_string(dir.join(report_name(day))) {
+        Ok(s) => s,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let r: DailyReport = serde_json::from_str(&s)?;
+    Ok(Some(r.try_into()?))
+}
+
+/// Write the summary to `<dir>/daily-YYYY-MM-DD.json` and prune reports older
+/// than the retention period.
+pub fn export_daily(dir: &Path, summary: &DailySummary) -> Result<PathBuf, anyhow::Error> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(report_name(summary.day));
+    let json = serde_json::to_string_pretty(&DailyReport::from(summary))?;
+    fs::write(&path, json + "\n")?;
+    prune(dir, summary.day - REPORT_RETENTION_DAYS)?;
+    Ok(path)
+}
+
+fn report_name(day: i64) -> String {
+    format!("daily-{}.json", format_day(day))
+}
+
+/// Remove reports dated before `cutoff` (days since epoch).
+fn prune(dir: &Path, cutoff: i64) -> Result<(), anyhow::Error> {
+    let cutoff = report_name(cutoff);
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        // Dates are zero-padded, so names sort chronologically.
+        if name.starts_with("daily-") && name.ends_with(".json") && *name < *cutoff {
+            fs::remove_file(dir.join(name.as_ref()))?;
+        }
+    }
+    Ok(())
+}
+
+/// The fields of a daily report needed to track battery health.
+#[derive(Debug, Deserialize)]
+struct HealthFields {
+    day: String,
+    charge_full: Option<i64>,
+    charge_full_design: Option<i64>,
+}
+
+/// Battery health for every report in `dir` that recorded it.
+fn report_health(dir: &Path) -> Result<Vec<HealthPoint>, anyhow::Error> {
+    let mut points = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(points),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let is_report = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with("daily-") && n.ends_with(".json"));
+        if !is_report {
+            continue;
+        }
+        let Ok(r) = serde_json::from_str::<HealthFields>(&fs::read_to_string(&path)?) else {
+            continue;
+        };
+        if let (Some(day), Some(full), Some(design)) =
+            (parse_day(&r.day), r.charge_full, r.charge_full_design)
+        {
+            if design > 0 {
+                points.push(HealthPoint {
+                    day,
+                    percent: full as f64 * 100.0 / design as f64,
+                });
+            }
+        }
+    }
+    points.sort_by_key(|p| p.day);
+    Ok(points)
+}
+
+/// A line of the health history.
+#[derive(Debug, Serialize, Deserialize)]
+struct HealthLine {
+    day: String,
+    health_percent: f64,
+}
+
+/// The health history in `dir`, after adding the days before `today` that
+/// have a report but aren't in it yet. Reports are pruned after a month,
+/// the history never is.
+pub fn update_health(dir: &Path, today: i64) -> Result<Vec<HealthPoint>, anyhow::Error> {
+    let path = dir.join(HEALTH_FILE);
+    let mut points = Vec::new();
+    match fs::read_to_string(&path) {
+        Ok(s) => {
+            for line in s.lines().filter(|l| !l.trim().is_empty()) {
+                let Ok(l) = serde_json::from_str::<HealthLine>(line) else {
+                    continue;
+                };
+                if let Some(day) = parse_day(&l.day) {
+                    points.push(HealthPoint {
+                        day,
+                        percent: l.health_percent,
+                    });
+                }
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    let last = points.iter().map(|p| p.day).max();
+    for p in report_health(dir)? {
+        // Today's report is still changing.
+        if p.day < today && last.is_none_or(|last| p.day > last) {
+            let line = HealthLine {
+                day: format_day(p.day),
+                health_percent: p.percent,
+            };
+            state::append_line(&path, &serde_json::to_string(&line)?)?;
+            points.push(p);
+        }
+    }
+    points.sort_by_key(|p| p.day);
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::health::HealthPoint;
+    use crate::report::{export_daily, load_daily, update_health, REPORT_RETENTION_DAYS};
+    use crate::summary::DailySummary;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn export_writes_report_and_prunes_old_ones() {
+        let dir = TestDir::new("report");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("daily-1970-01-01.json"), "{}").unwrap();
+        fs::write(dir.join("unrelated.txt"), "").unwrap();
+
+        let mut s = DailySummary::new(REPORT_RETENTION_DAYS + 1);
+        s.record_capacity(77);
+        s.record_health(4_500_000, 5_000_000);
+        let path = export_daily(&dir, &s).unwrap();
+
+        assert_eq!("daily-1970-02-01.json", path.file_name().unwrap());
+        let json = fs::read_to_string(&path).unwrap();
+        assert!(json.contains("\"min_capacity\": 77"));
+        assert!(!dir.join("daily-1970-01-01.json").exists());
+        assert!(dir.join("unrelated.txt").exists());
+        let loaded = load_daily(&dir, REPORT_RETENTION_DAYS + 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(s.to_string(), loaded.to_string());
+        assert_eq!(Some(5_000_000), loaded.charge_full_design);
+        assert!(load_daily(&dir, 0).unwrap().is_none());
+        let day = REPORT_RETENTION_DAYS + 1;
+        assert!(update_health(&dir, day).unwrap().is_empty());
+        let point = HealthPoint { day, percent: 90.0 };
+        assert_eq!(vec![point], update_health(&dir, day + 1).unwrap());
+
+        // The history outlives the reports, and each day is in it once.
+        let mut s = s.next(day + 1);
+        s.record_health(4_400_000, 5_000_000);
+        export_daily(&dir, &s).unwrap();
+        export_daily(&dir, &DailySummary::new(day + REPORT_RETENTION_DAYS + 1)).unwrap();
+        assert!(load_daily(&dir, day).unwrap().is_none());
+        let later = HealthPoint {
+            day: day + 1,
+            percent: 88.0,
+        };
+        assert_eq!(vec![point, later], update_health(&dir, day + 40).unwrap());
+        assert_eq!(vec![point, later], update_health(&dir, day + 40).unwrap());
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::anyhow;
+
+/// Charge rate assumed until one has been measured, in percent per hour.
+pub const DEFAULT_RATE: f64 = 20.0;
+
+/// Extra time allowed for charging slowing down near full.
+const MARGIN_SECS: i64 = 30 * 60;
+
+/// Capacity gained before a charge rate sample is taken.
+const SAMPLE_POINTS: i8 = 5;
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// A local time of day the battery should be full by, on some days of the week.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    /// Seconds after local midnight.
+    time: i64,
+    /// Monday first.
+    days: [bool; 7],
+}
+
+impl Schedule {
+    /// Parse a `HH:MM` time and day names (`mon`..`sun`), every day if empty.
+    pub fn parse(time: &str, days: &[String]) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            time: parse_time(time)?,
+            days: parse_days(days)?,
+        })
+    }
+
+    /// The first deadline after `local` (local seconds since the epoch).
+    fn next_deadline(&self, local: i64) -> i64 {
+        let today = local.div_euclid(86400);
+        (0..=7)
+            .map(|d| today + d)
+            .filter(|&day| self.days[weekday(day)])
+            .map(|day| day * 86400 + self.time)
+            .find(|&t| t > local)
+            .expect("at least one day is enabled")
+    }
+}
+
+/// Seconds after midnight of a `HH:MM` time.
+pub fn parse_time(time: &str) -> Result<i64, anyhow::Error> {
+    let invalid = || anyhow!("Invalid time {time:?}, expected HH:MM");
+    let (h, m) = time.split_once(':').ok_or_else(invalid)?;
+    let (h, m) = (
+        h.parse::<i64>().map_err(|_| invalid())?,
+        m.parse::<i64>().map_err(|_| invalid())?,
+    );
+    if !(0..24).contains(&h) || !(0..60).contains(&m) {
+        return Err(invalid());
+    }
+    Ok(h * 3600 + m * 60)
+}
+
+/// Which days of the week, Monday first, are named. All of them if none are.
+pub fn parse_days(days: &[String]) -> Result<[bool; 7], anyhow::Error> {
+    let mut mask = [days.is_empty(); 7];
+    for d in days {
+        let i = DAYS
+            .iter()
+            .position(|n| d.eq_ignore_ascii_case(n))
+            .ok_or_else(|| anyhow!("Invalid day {d:?}, expected one of {}", DAYS.join(", ")))?;
+        mask[i] = true;
+    }
+    Ok(mask)
+}
+
+/// Day of the week, Monday as 0, of a day since the epoch.
+pub fn weekday(day: i64) -> usize {
+    // 1970-01-01 was a Thursday.
+    (day + 3).rem_euclid(7) as usize
+}
+
+/// Tracks how fast the battery charges, from capacity gained over time.
+#[derive(Debug)]
+pub struct ChargeRate {
+    /// Percent per hour.
+    per_hour: f64,
+    /// When and at what capacity the current sample started.
+    since: Option<(u64, i8)>,
+}
+
+impl ChargeRate {
+    pub fn new() -> Self {
+        Self {
+            per_hour: DEFAULT_RATE,
+            since: None,
+        }
+    }
+
+    pub fn observe(&mut self, now: u64, capacity: i8, charging: bool) {
+        match self.since {
+            Some((t, c)) if charging && capacity - c >= SAMPLE_POINTS && now > t => {
+                let sample = f64::from(capacity - c) * 3600.0 / (now - t) as f64;
+                self.per_hour = (self.per_hour + sample.clamp(1.0, 200.0)) / 2.0;
+                self.since = Some((now, capacity));
+            }
+            Some((_, c)) if charging && capacity >= c => {}
+            _ if charging => self.since = Some((now, capacity)),
+            _ => self.since = None,
+        }
+    }
+
+    /// Estimated seconds to charge from `capacity` to full.
+    fn secs_to_full(&self, capacity: i8) -> i64 {
+        (f64::from(100 - capacity.min(100)) / self.per_hour * 3600.0) as i64
+    }
+}
+
+/// Decides when to start charging to meet a schedule.
+#[derive(Debug)]
+pub struct FullBy {
+    schedule: Schedule,
+    pub rate: ChargeRate,
+    /// Deadline (UTC seconds) of the charge in progress.
+    active: Option<u64>,
+}
+
+impl FullBy {
+    pub fn new(schedule: Schedule) -> Self {
+        Self {
+            schedule,
+            rate: ChargeRate::new(),
+            active: None,
+        }
+    }
+
+    /// Whether charging to full should be allowed now. `offset` is local
+    /// time's offset from UTC.
+    pub fn step(&mut self, now: u64, offset: i64, capacity: i8) -> bool {
+        if self.active.is_some_and(|d| now >= d) {
+            self.active = None;
+        }
+        if self.active.is_none() {
+            let local = now as i64 + offset;
+            let deadline = self.schedule.next_deadline(local);
+            let left = deadline - local;
+            if left <= self.rate.secs_to_full(capacity) + MARGIN_SECS {
+                self.active = Some((deadline - offset) as u64);
+            }
+        }
+        self.active.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schedule::{ChargeRate, FullBy, Schedule, DEFAULT_RATE};
+
+    // 2023-04-03, a Monday, at 00:00 UTC.
+    const MONDAY: u64 = 19450 * 86400;
+    const HOUR: u64 = 3600;
+
+    #[test]
+    fn parse_schedules() {
+        let weekdays: Vec<String> = ["mon", "tue", "wed", "thu", "fri"]
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        let s = Schedule::parse("07:30", &weekdays).unwrap();
+        assert_eq!([true, true, true, true, true, false, false], s.days);
+        assert_eq!(7 * 3600 + 30 * 60, s.time);
+        assert_eq!([true; 7], Schedule::parse("07:30", &[]).unwrap().days);
+        assert!(Schedule::parse("7", &[]).is_err());
+        assert!(Schedule::parse("24:00", &[]).is_err());
+        assert!(Schedule::parse("07:30", &["someday".to_string()]).is_err());
+    }
+
+    #[test]
+    fn next_deadline_skips_disabled_days() {
+        let s = Schedule::parse("07:30", &["mon".to_string()]).unwrap();
+        let monday_0730 = MONDAY as i64 + 7 * 3600 + 1800;
+        assert_eq!(monday_0730, s.next_deadline(MONDAY as i64));
+        assert_eq!(monday_0730 + 7 * 86400, s.next_deadline(monday_0730));
+    }
+
+    #[test]
+    fn charge_rate_is_measured_while_charging() {
+        let mut r = ChargeRate::new();
+        r.observe(0, 50, true);
+        r.observe(HOUR / 2, 54, true);
+        assert_eq!(DEFAULT_RATE, r.per_hour);
+        r.observe(HOUR, 60, true);
+        assert_eq!((DEFAULT_RATE + 10.0) / 2.0, r.per_hour);
+        r.observe(2 * HOUR, 60, false);
+        assert_eq!(None, r.since);
+    }
+
+    #[test]
+    fn starts_early_enough_and_ends_at_deadline() {
+        let mut f = FullBy::new(Schedule::parse("07:30", &[]).unwrap());
+        // 20%/h from 60% needs 2h plus the margin, so charging starts at 05:00.
+        assert!(!f.step(MONDAY + 4 * HOUR, 0, 60));
+        assert!(f.step(MONDAY + 5 * HOUR, 0, 60));
+        assert!(f.step(MONDAY + 7 * HOUR, 0, 100));
+        assert!(!f.step(MONDAY + 7 * HOUR + 1800, 0, 100));
+
+        // In UTC+2 07:30 local is 05:30 UTC.
+        let mut f = FullBy::new(Schedule::parse("07:30", &[]).unwrap());
+        assert!(!f.step(MONDAY + 2 * HOUR, 2 * 3600, 60));
+        assert!(f.step(MONDAY + 3 * HOUR, 2 * 3600, 60));
+        assert!(!f.step(MONDAY + 5 * HOUR + 1800, 2 * 3600, 100));
+    }
+}
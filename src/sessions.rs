@@ -0,0 +1,249 @@
+use std::path::PathBuf;
+use std::time::Instant;
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::events::{Event, Subscriber};
+use crate::ChargeBehaviour;
+use crate::{clock, state};
+
+/// Summary of one period on AC, from plug-in to unplug.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Session {
+    pub start: String,
+    pub end: String,
+    pub duration_secs: u64,
+    pub start_capacity: i8,
+    pub end_capacity: i8,
+    pub energy_added_uwh: i64,
+    pub behaviours: Vec<String>,
+}
+
+struct Current {
+    start: u64,
+    started: Instant,
+    start_capacity: i8,
+    energy_added: i64,
+    behaviours: Vec<ChargeBehaviour>,
+}
+
+impl Current {
+    fn saw(&mut self, b: ChargeBehaviour) {
+        if !self.behaviours.contains(&b) {
+            self.behaviours.push(b);
+        }
+    }
+}
+
+/// Detects charging sessions from AC changes and appends a summary of each
+/// to a JSON lines file.
+pub struct SessionTracker {
+    path: PathBuf,
+    current: Option<Current>,
+    capacity: Option<i8>,
+    behaviour: Option<ChargeBehaviour>,
+    energy: Option<i64>,
+}
+
+impl SessionTracker {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            current: None,
+            capacity: None,
+            behaviour: None,
+            energy: None,
+        }
+    }
+
+    fn finish(&mut self) -> Option<Session> {
+        let c = self.current.take()?;
+        Some(Session {
+            start: clock::format_timestamp(c.start),
+            end: clock::format_timestamp(clock::now()),
+            duration_secs: c.started.elapsed().as_secs(),
+            start_capacity: c.start_capacity,
+            end_capacity: self.capacity.unwrap_or(c.start_capacity),
+            energy_added_uwh: c.energy_added,
+            behaviours: c.behaviours.iter().map(|b| b.to_string()).collect(),
+        })
+    }
+
+    fn append(&self, session: &Session) -> Result<(), anyhow::Error> {
+        state::append_line(&self.path, &serde_json::to_string(session)?)
+    }
+}
+
+impl Subscriber for SessionTracker {
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::CapacityRead {
+                capacity,
+                behaviour,
+                energy,
+                ..
+            } => {
+                if let Some(c) = self.current.as_mut() {
+                    if let (Some(last), Some(now)) = (self.energy, energy) {
+                        c.energy_added += (now - last).max(0);
+                    }
+                    c.saw(*behaviour);
+                }
+                self.capacity = Some(*capacity);
+                self.behaviour = Some(*behaviour);
+                self.energy = *energy;
+            }
+            Event::TransitionApplied { new, .. } => {
+                if let Some(c) = self.current.as_mut() {
+                    c.saw(*new);
+                }
+            }
+            Event::AcChanged { online: true } => {
+                if let Some(capacity) = self.capacity {
+                    self.current = Some(Current {
+                        start: clock::now(),
+                        started: Instant::now(),
+                        start_capacity: capacity,
+                        energy_added: 0,
+                        behaviours: self.behaviour.into_iter().collect(),
+                    });
+                }
+            }
+            Event::AcChanged { online: false } => {
+                if let Some(s) = self.finish() {
+                    info!(
+                        "Charging session ended: duration={}s start_capacity={} end_capacity={} energy_added={:.2}Wh behaviours={}",
+                        s.duration_secs,
+                        s.start_capacity,
+                        s.end_capacity,
+                        s.energy_added_uwh as f64 / 1_000_000.0,
+                        s.behaviours.join(",")
+                    );
+                    if let Err(e) = self.append(&s) {
+                        warn!("Could not record charging session: {e}");
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PlugEvent {
+    time: String,
+    online: bool,
+    capacity: Option<i8>,
+}
+
+/// Logs every AC plug and unplug, with the capacity at the time, to a JSON lines file.
+pub struct PlugLog {
+    path: PathBuf,
+    capacity: Option<i8>,
+}
+
+impl PlugLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            capacity: None,
+        }
+    }
+}
+
+impl Subscriber for PlugLog {
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::CapacityRead { capacity, .. } => self.capacity = Some(*capacity),
+            Event::AcChanged { online } => {
+                let cap = self.capacity.map_or("-".to_string(), |c| c.to_string());
+                if *online {
+                    info!("AC plugged in, battery at {cap}%");
+                } else {
+                    info!("AC unplugged, battery at {cap}%");
+                }
+                let e = PlugEvent {
+                    time: clock::format_timestamp(clock::now()),
+                    online: *online,
+                    capacity: self.capacity,
+                };
+                let res = serde_json::to_string(&e)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|line| state::append_line(&self.path, &line));
+                if let Err(e) = res {
+                    warn!("Could not record AC event: {e}");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::events::{Event, Subscriber};
+    use crate::reason::Reason;
+    use crate::sessions::{PlugLog, SessionTracker};
+    use crate::testdir::TestDir;
+    use crate::ChargeBehaviour;
+
+    fn read(capacity: i8, behaviour: ChargeBehaviour, energy: i64) -> Event {
+        Event::CapacityRead {
+            capacity,
+            behaviour,
+            ac_online: Some(true),
+            energy: Some(energy),
+            charge_full: None,
+        }
+    }
+
+    #[test]
+    fn session_from_plug_to_unplug() {
+        let dir = TestDir::new("sessions");
+        let path = dir.join("sessions.jsonl");
+        let mut t = SessionTracker::new(path.clone());
+
+        t.handle(&read(60, ChargeBehaviour::Auto, 40_000_000));
+        t.handle(&Event::AcChanged { online: true });
+        t.handle(&read(70, ChargeBehaviour::Auto, 45_000_000));
+        t.handle(&Event::TransitionApplied {
+            old: ChargeBehaviour::Auto,
+            new: ChargeBehaviour::InhibitCharge,
+            capacity: 80,
+            reason: Reason::WithinThresholds,
+        });
+        t.handle(&read(80, ChargeBehaviour::InhibitCharge, 50_000_000));
+        t.handle(&Event::AcChanged { online: false });
+
+        let s = fs::read_to_string(&path).unwrap();
+        assert_eq!(1, s.lines().count());
+        assert!(s.contains("\"start_capacity\":60,\"end_capacity\":80"));
+        assert!(s.contains("\"energy_added_uwh\":10000000"));
+        assert!(s.contains("\"behaviours\":[\"auto\",\"inhibit-charge\"]"));
+
+        // Unplugging again without a session doesn't record anything.
+        t.handle(&Event::AcChanged { online: false });
+        assert_eq!(1, fs::read_to_string(&path).unwrap().lines().count());
+    }
+
+    #[test]
+    fn plug_events_are_recorded() {
+        let dir = TestDir::new("plugs");
+        let path = dir.join("plugs.jsonl");
+        let mut l = PlugLog::new(path.clone());
+
+        l.handle(&read(55, ChargeBehaviour::Auto, 0));
+        l.handle(&Event::AcChanged { online: true });
+        l.handle(&read(57, ChargeBehaviour::Auto, 0));
+        l.handle(&Event::AcChanged { online: false });
+
+        let s = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].ends_with("\"online\":true,\"capacity\":55}"));
+        assert!(lines[1].ends_with("\"online\":false,\"capacity\":57}"));
+    }
+}
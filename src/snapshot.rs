@@ -0,0 +1,111 @@
+use crate::{get_behaviour, sysfs, ChargeBehaviour};
+
+/// Everything read from the power_supply devices in one evaluation, so
+/// policy, history and metrics all see the same values. Optional attributes
+/// are `None` if missing or unreadable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub capacity: i8,
+    pub behaviour: ChargeBehaviour,
+    /// The battery's `status`, e.g. "Charging" or "Full".
+    pub status: Option<String>,
+    pub ac_online: Option<bool>,
+    /// Why `ac_online` couldn't be read.
+    pub ac_error: Option<String>,
+    /// Battery temperature in tenths of a degree Celsius.
+    pub temp: Option<i32>,
+    /// µW flowing in or out of the battery.
+    pub power_now: Option<i64>,
+    /// µWh.
+    pub energy: Option<i64>,
+    /// Full charge capacity and its design value, in µAh.
+    pub charge_full: Option<(i64, i64)>,
+}
+
+fn read_battery<T: std::str::FromStr>(attr: &str) -> Option<T> {
+    sysfs::read(sysfs::battery(attr))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+}
+
+impl Snapshot {
+    /// Read all attributes in one pass. Fails only if capacity or charge
+    /// behaviour can't be read.
+    pub fn read() -> Result<Self, anyhow::Error> {
+        let capacity = sysfs::read(sysfs::battery("capacity"))?
+            .trim()
+            .parse::<i8>()?;
+        let behaviour = get_behaviour()?;
+        let (ac_online, ac_error) = match sysfs::read(sysfs::ac("online")) {
+            Ok(s) => (Some(s.trim() == "1"), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Ok(Self {
+            capacity,
+            behaviour,
+            status: read_battery("status"),
+            ac_online,
+            ac_error,
+            temp: read_battery("temp"),
+            power_now: read_battery("power_now"),
+            energy: read_battery("energy_now"),
+            charge_full: read_battery("charge_full").zip(read_battery("charge_full_design")),
+        })
+    }
+
+    /// Describes why the attributes contradict each other, as happens when
+    /// they're read while the driver is changing state.
+    pub fn inconsistency(&self) -> Option<&'static str> {
+        let status = self.status.as_deref();
+        if !(0..=100).contains(&self.capacity) {
+            Some("capacity is out of range")
+        } else if status == Some("Charging") && self.ac_online == Some(false) {
+            Some("charging while AC is offline")
+        } else if status == Some("Charging") && self.behaviour != ChargeBehaviour::Auto {
+            Some("charging while charging is inhibited")
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::Snapshot;
+    use crate::ChargeBehaviour;
+
+    #[test]
+    fn detect_inconsistent_snapshots() {
+        let ok = Snapshot {
+            capacity: 75,
+            behaviour: ChargeBehaviour::Auto,
+            status: Some("Charging".to_string()),
+            ac_online: Some(true),
+            ac_error: None,
+            temp: None,
+            power_now: None,
+            energy: None,
+            charge_full: None,
+        };
+        assert_eq!(None, ok.inconsistency());
+
+        let unplugged = Snapshot {
+            ac_online: Some(false),
+            ..ok.clone()
+        };
+        assert!(unplugged.inconsistency().is_some());
+
+        let inhibited = Snapshot {
+            behaviour: ChargeBehaviour::InhibitCharge,
+            ..ok.clone()
+        };
+        assert!(inhibited.inconsistency().is_some());
+
+        let negative = Snapshot {
+            capacity: -1,
+            status: None,
+            ..ok
+        };
+        assert!(negative.inconsistency().is_some());
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::Display;
+
+use crate::reason::{Override, Reason};
+use crate::ChargeBehaviour;
+
+/// Who gets to decide the charge behaviour, lowest priority first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// The policy with the configured thresholds, or ones from storage mode,
+    /// `set-thresholds` or the desktop settings.
+    Default,
+    /// The policy with the thresholds of the active profile.
+    Profile,
+    /// Full-by schedules, calibration and maintenance windows.
+    Schedule,
+    /// A weak charger and force-discharge not working.
+    Failsafe,
+    /// Asked for by someone: drains, travel mode, full charges, changes made
+    /// by hand and the startup behaviour.
+    Manual,
+    /// The temperature limit, maintenance windows ruling out force-discharge
+    /// and the hibernate floor, which nothing overrides.
+    Limit,
+}
+
+impl Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Priority::Default => "default",
+            Priority::Profile => "profile",
+            Priority::Schedule => "schedule",
+            Priority::Failsafe => "failsafe",
+            Priority::Manual => "manual",
+            Priority::Limit => "limit",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// A behaviour wanted for a reason.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layer {
+    pub priority: Priority,
+    pub behaviour: ChargeBehaviour,
+    pub reason: Reason,
+}
+
+/// Everything that wants a charge behaviour in one evaluation. The layer
+/// with the highest priority decides, among equals the one added last.
+#[derive(Debug)]
+pub struct Stack {
+    layers: Vec<Layer>,
+}
+
+impl Stack {
+    /// Start from what the policy wants.
+    pub fn new(priority: Priority, behaviour: ChargeBehaviour, reason: Reason) -> Self {
+        let mut s = Self { layers: Vec::new() };
+        s.push(priority, behaviour, reason);
+        s
+    }
+
+    pub fn push(&mut self, priority: Priority, behaviour: ChargeBehaviour, reason: Reason) {
+        self.layers.push(Layer {
+            priority,
+            behaviour,
+            reason,
+        });
+    }
+
+    fn top(&self) -> &Layer {
+        // max_by_key returns the last of equal elements.
+        self.layers
+            .iter()
+            .max_by_key(|l| l.priority)
+            .expect("the policy is always on the stack")
+    }
+
+    /// The behaviour that wins, and why.
+    pub fn decide(&self) -> (ChargeBehaviour, Reason) {
+        let top = self.top();
+        (top.behaviour, top.reason)
+    }
+
+    /// Priority of the layer that wins.
+    pub fn priority(&self) -> Priority {
+        self.top().priority
+    }
+
+    /// All layers, the one that wins first.
+    pub fn layers(&self) -> Vec<Layer> {
+        let mut layers = self.layers.clone();
+        layers.reverse();
+        // Stable, so equals stay last added first.
+        layers.sort_by_key(|l| std::cmp::Reverse(l.priority));
+        layers
+    }
+
+    /// The override with the highest priority on the stack, if any. Full-by
+    /// schedules are on it as schedule windows.
+    pub fn active_override(&self) -> Option<Override> {
+        self.layers().iter().find_map(|l| match l.reason {
+            Reason::OverrideActive(o) => Some(o),
+            Reason::ScheduleWindow => Some(Override::FullBy),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reason::{Failsafe, Override, Reason};
+    use crate::stack::{Priority, Stack};
+    use crate::ChargeBehaviour::{Auto, ForceDischarge, InhibitCharge};
+
+    #[test]
+    fn highest_priority_decides() {
+        let mut s = Stack::new(Priority::Default, InhibitCharge, Reason::WithinThresholds);
+        assert_eq!((InhibitCharge, Reason::WithinThresholds), s.decide());
+
+        let drain = Reason::OverrideActive(Override::Drain);
+        s.push(Priority::Manual, ForceDischarge, drain);
+        let weak = Reason::Failsafe(Failsafe::WeakCharger);
+        s.push(Priority::Failsafe, InhibitCharge, weak);
+        s.push(Priority::Schedule, Auto, Reason::ScheduleWindow);
+        assert_eq!((ForceDischarge, drain), s.decide());
+        assert_eq!(Priority::Manual, s.priority());
+
+        let floor = Reason::Failsafe(Failsafe::HibernateFloor);
+        s.push(Priority::Limit, Auto, floor);
+        assert_eq!((Auto, floor), s.decide());
+    }
+
+    #[test]
+    fn limits_override_a_drain() {
+        let mut s = Stack::new(Priority::Default, InhibitCharge, Reason::WithinThresholds);
+        let drain = Reason::OverrideActive(Override::Drain);
+        s.push(Priority::Manual, ForceDischarge, drain);
+        let maintenance = Reason::OverrideActive(Override::Maintenance);
+        s.push(Priority::Limit, Auto, maintenance);
+        assert_eq!((Auto, maintenance), s.decide());
+
+        assert_eq!(Some(Override::Maintenance), s.active_override());
+
+        s.push(Priority::Limit, InhibitCharge, Reason::ThermalLimit);
+        assert_eq!((InhibitCharge, Reason::ThermalLimit), s.decide());
+        assert_eq!(Priority::Limit, s.priority());
+        assert_eq!(Some(Override::Maintenance), s.active_override());
+    }
+
+    #[test]
+    fn overrides_are_found_on_the_stack() {
+        let mut s = Stack::new(Priority::Default, InhibitCharge, Reason::WithinThresholds);
+        assert_eq!(None, s.active_override());
+        s.push(Priority::Schedule, Auto, Reason::ScheduleWindow);
+        assert_eq!(Some(Override::FullBy), s.active_override());
+        let external = Reason::OverrideActive(Override::External);
+        s.push(Priority::Manual, ForceDischarge, external);
+        assert_eq!(Some(Override::External), s.active_override());
+    }
+
+    #[test]
+    fn last_added_wins_among_equals() {
+        let mut s = Stack::new(Priority::Default, InhibitCharge, Reason::WithinThresholds);
+        let calibration = Reason::OverrideActive(Override::Calibration);
+        let maintenance = Reason::OverrideActive(Override::Maintenance);
+        s.push(Priority::Schedule, ForceDischarge, calibration);
+        s.push(Priority::Schedule, Auto, maintenance);
+        assert_eq!((Auto, maintenance), s.decide());
+
+        let order: Vec<Reason> = s.layers().iter().map(|l| l.reason).collect();
+        assert_eq!(
+            vec![maintenance, calibration, Reason::WithinThresholds],
+            order
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+const DEFAULT_STATE_DIR: &str = "/var/lib/macsmc-charged";
+
+/// Directory for persistent state. Uses `$STATE_DIRECTORY` as set by systemd's
+/// `StateDirectory=`, falling back to /var/lib/macsmc-charged.
+pub fn state_dir() -> PathBuf {
+    match std::env::var_os("STATE_DIRECTORY") {
+        // systemd may pass a colon-separated list; the first entry is ours.
+        Some(s) if !s.is_empty() => s
+            .to_string_lossy()
+            .split(':')
+            .next()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_DIR)),
+        _ => PathBuf::from(DEFAULT_STATE_DIR),
+    }
+}
+
+/// Append a single line to the file at `path`, creating it and its parent
+/// directory if needed.
+pub fn append_line(path: &Path, line: &str) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(f, "{line}")?;
+    Ok(())
+}
+
+/// A request or flag for the running daemon, kept as a small file in the
+/// state directory so the command line and the daemon can share it.
+pub struct RequestFile(&'static str);
+
+impl RequestFile {
+    pub const fn new(name: &'static str) -> Self {
+        Self(name)
+    }
+
+    fn path(&self, dir: &Path) -> PathBuf {
+        dir.join(self.0)
+    }
+
+    /// Make the request, replacing any pending one.
+    pub fn write(&self, dir: &Path, contents: &str) -> Result<(), anyhow::Error> {
+        fs::create_dir_all(dir)?;
+        fs::write(self.path(dir), format!("{contents}\n"))?;
+        Ok(())
+    }
+
+    /// The pending request with surrounding whitespace trimmed, if any.
+    pub fn read(&self, dir: &Path) -> Result<Option<String>, anyhow::Error> {
+        match fs::read_to_string(self.path(dir)) {
+            Ok(s) => Ok(Some(s.trim().to_string())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn exists(&self, dir: &Path) -> bool {
+        self.path(dir).exists()
+    }
+
+    pub fn clear(&self, dir: &Path) -> Result<(), anyhow::Error> {
+        match fs::remove_file(self.path(dir)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state::RequestFile;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn request_file_roundtrip() {
+        let dir = TestDir::new("state");
+        let file = RequestFile::new("request");
+
+        assert_eq!(None, file.read(&dir).unwrap());
+        assert!(!file.exists(&dir));
+        file.write(&dir, "60 80").unwrap();
+        assert!(file.exists(&dir));
+        assert_eq!(Some("60 80".to_string()), file.read(&dir).unwrap());
+        file.clear(&dir).unwrap();
+        assert_eq!(None, file.read(&dir).unwrap());
+        file.clear(&dir).unwrap();
+    }
+}
@@ -0,0 +1,276 @@
+use std::fmt::Display;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::forecast::Point;
+
+fn status_path(dir: &Path) -> PathBuf {
+    dir.join("status.json")
+}
+
+/// One of the layers that wanted a behaviour, see [`crate::stack`].
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct StackEntry {
+    pub priority: String,
+    pub behaviour: String,
+    pub reason: String,
+}
+
+/// Battery health and when the battery is expected to need replacing.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    /// Full charge capacity as a percentage of design.
+    pub percent: f64,
+    pub per_month: f64,
+    /// Day the health is expected to reach `replace_at`, if declining.
+    pub replace_by: Option<String>,
+}
+
+/// What the running daemon last saw and did, for clients to read.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    /// When this was written, RFC 3339.
+    pub updated: String,
+    pub capacity: i8,
+    pub behaviour: String,
+    pub ac_online: Option<bool>,
+    pub low_threshold: i8,
+    pub high_threshold: i8,
+    /// Reason of the override in effect, if any.
+    pub active_override: Option<String>,
+    /// Profile the thresholds come from, if any.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Memory used by the daemon.
+    #[serde(default)]
+    pub rss_kib: Option<u64>,
+    /// When the daemon last changed the charge behaviour, RFC 3339.
+    #[serde(default)]
+    pub last_transition: Option<String>,
+    /// What wanted a behaviour in the last evaluation, the one that won first.
+    #[serde(default)]
+    pub stack: Vec<StackEntry>,
+    /// Capacity a firmware charge limit appears to hold the battery at.
+    #[serde(default)]
+    pub firmware_limit: Option<i8>,
+    /// From the daily health history, once there is enough of it.
+    #[serde(default)]
+    pub health: Option<Health>,
+    /// Expected capacity over the next hours.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forecast: Vec<Point>,
+}
+
+impl Status {
+    /// What the daemon is going to do next, in words.
+    pub fn next_step(&self) -> String {
+        let (low, high) = (self.low_threshold, self.high_threshold);
+        if let Some(o) = &self.active_override {
+            return format!("following {o} until it ends");
+        }
+        match (self.behaviour.as_str(), self.ac_online) {
+            (_, Some(false)) => "on battery, the behaviour is set once plugged in".to_string(),
+            ("force-discharge", _) => format!("discharging, then holding at {high}%"),
+            ("inhibit-charge", _) => format!("holding, charging again below {low}%"),
+            ("auto", _) if self.capacity >= high => format!("holding at {high}%"),
+            ("auto", _) => format!("charging to {high}%, then holding"),
+            _ => "unknown".to_string(),
+        }
+    }
+}
+
+impl Status {
+    /// The status as a Waybar custom module line: behaviour and capacity as
+    /// the text, the rest of the status as the tooltip and the behaviour as
+    /// class.
+    pub fn waybar(&self) -> String {
+        let short = match self.behaviour.as_str() {
+            "inhibit-charge" => "inhibit",
+            "force-discharge" => "discharge",
+            b => b,
+        };
+        // Leave out what changes every cycle, so following only prints changes.
+        let tooltip: Vec<String> = self
+            .to_string()
+            .lines()
+            .filter(|l| !l.starts_with("memory:") && !l.starts_with("updated:"))
+            .map(str::to_string)
+            .collect();
+        serde_json::json!({
+            "text": format!("{short} {}%", self.capacity),
+            "tooltip": tooltip.join("\n"),
+            "class": self.behaviour,
+            "percentage": self.capacity,
+        })
+        .to_string()
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "capacity:   {}%", self.capacity)?;
+        writeln!(f, "behaviour:  {}", self.behaviour)?;
+        writeln!(
+            f,
+            "ac:         {}",
+            match self.ac_online {
+                Some(true) => "online",
+                Some(false) => "offline",
+                None => "unknown",
+            }
+        )?;
+        writeln!(
+            f,
+            "thresholds: {}-{}%",
+            self.low_threshold, self.high_threshold
+        )?;
+        if let Some(profile) = &self.profile {
+            writeln!(f, "profile:    {profile}")?;
+        }
+        writeln!(
+            f,
+            "override:   {}",
+            self.active_override.as_deref().unwrap_or("none")
+        )?;
+        if let Some(c) = self.firmware_limit {
+            writeln!(f, "firmware:   appears to stop charging at {c}%")?;
+        }
+        if let Some(h) = &self.health {
+            write!(
+                f,
+                "health:     {:.1}% of design, {:+.2}%/month",
+                h.percent, h.per_month
+            )?;
+            match &h.replace_by {
+                Some(day) => writeln!(f, ", replace by {day}")?,
+                None => writeln!(f)?,
+            }
+        }
+        if self.stack.len() > 1 {
+            let layers: Vec<String> = self
+                .stack
+                .iter()
+                .map(|l| format!("{} ({}, {})", l.reason, l.priority, l.behaviour))
+                .collect();
+            writeln!(f, "stack:      {}", layers.join(" > "))?;
+        }
+        writeln!(f, "next:       {}", self.next_step())?;
+        let hourly: Vec<String> = self
+            .forecast
+            .iter()
+            .filter(|p| p.minutes % 60 == 0)
+            .map(|p| format!("{}% in {}h", p.capacity, p.minutes / 60))
+            .collect();
+        if !hourly.is_empty() {
+            writeln!(f, "forecast:   {}", hourly.join(", "))?;
+        }
+        if let Some(t) = &self.last_transition {
+            writeln!(f, "changed:    {t}")?;
+        }
+        if let Some(rss) = self.rss_kib {
+            writeln!(f, "memory:     {rss} KiB")?;
+        }
+        write!(f, "updated:    {}", self.updated)
+    }
+}
+
+/// Replace the status file, without readers ever seeing a partial write.
+pub fn write(dir: &Path, status: &Status) -> Result<(), anyhow::Error> {
+    fs::create_dir_all(dir)?;
+    let tmp = dir.join("status.json.tmp");
+    fs::write(&tmp, serde_json::to_string_pretty(status)?)?;
+    fs::rename(tmp, status_path(dir))?;
+    Ok(())
+}
+
+/// How long ago the status was last written, if it has been.
+pub fn age(dir: &Path) -> Option<Duration> {
+    fs::metadata(status_path(dir))
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+}
+
+/// The last status written by the daemon, if it has written one.
+pub fn read(dir: &Path) -> Result<Option<Status>, anyhow::Error> {
+    match fs::read_to_string(status_path(dir)) {
+        Ok(s) => Ok(Some(serde_json::from_str(&s)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::forecast::Point;
+    use crate::status::{read, write, Health, StackEntry, Status};
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn status_roundtrip() {
+        let dir = TestDir::new("status");
+        assert_eq!(None, read(&dir).unwrap());
+
+        let status = Status {
+            updated: "1970-01-01T00:00:00Z".to_string(),
+            capacity: 75,
+            behaviour: "auto".to_string(),
+            ac_online: Some(true),
+            low_threshold: 70,
+            high_threshold: 80,
+            active_override: None,
+            profile: Some("desk".to_string()),
+            rss_kib: Some(2048),
+            last_transition: Some("1970-01-01T00:00:00Z".to_string()),
+            stack: vec![
+                StackEntry {
+                    priority: "profile".to_string(),
+                    behaviour: "auto".to_string(),
+                    reason: "below low threshold".to_string(),
+                },
+                StackEntry {
+                    priority: "default".to_string(),
+                    behaviour: "inhibit-charge".to_string(),
+                    reason: "within thresholds".to_string(),
+                },
+            ],
+            firmware_limit: Some(78),
+            health: Some(Health {
+                percent: 88.04,
+                per_month: -0.25,
+                replace_by: Some("2029-03-01".to_string()),
+            }),
+            forecast: vec![Point {
+                minutes: 30,
+                capacity: 80,
+            }],
+        };
+        write(&dir, &status).unwrap();
+        assert_eq!(Some(&status), read(&dir).unwrap().as_ref());
+        assert!(status.to_string().contains("thresholds: 70-80%\n"));
+        assert!(status
+            .to_string()
+            .contains("firmware:   appears to stop charging at 78%\n"));
+        assert!(status
+            .to_string()
+            .contains("health:     88.0% of design, -0.25%/month, replace by 2029-03-01\n"));
+        assert!(status.to_string().contains(
+            "stack:      below low threshold (profile, auto) > within thresholds (default, inhibit-charge)\n"
+        ));
+        assert!(status
+            .to_string()
+            .contains("next:       charging to 80%, then holding\n"));
+        let status = Status {
+            behaviour: "inhibit-charge".to_string(),
+            ..status
+        };
+        assert_eq!("holding, charging again below 70%", status.next_step());
+        let bar: serde_json::Value = serde_json::from_str(&status.waybar()).unwrap();
+        assert_eq!("inhibit 75%", bar["text"]);
+        assert_eq!("inhibit-charge", bar["class"]);
+    }
+}
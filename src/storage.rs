@@ -0,0 +1,36 @@
+use std::path::Path;
+
+use crate::state::RequestFile;
+
+/// Percentage points below the storage level at which charging resumes.
+pub const HYSTERESIS: i8 = 5;
+
+const FILE: RequestFile = RequestFile::new("storage");
+
+pub fn enable(dir: &Path) -> Result<(), anyhow::Error> {
+    FILE.write(dir, "")
+}
+
+pub fn disable(dir: &Path) -> Result<(), anyhow::Error> {
+    FILE.clear(dir)
+}
+
+pub fn active(dir: &Path) -> bool {
+    FILE.exists(dir)
+}
+
+/// Thresholds that hold the battery at `level`.
+pub fn thresholds(level: i8) -> (i8, i8) {
+    ((level - HYSTERESIS).max(0), level.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::thresholds;
+
+    #[test]
+    fn thresholds_hold_the_level() {
+        assert_eq!((45, 50), thresholds(50));
+        assert_eq!((0, 3), thresholds(3));
+    }
+}
@@ -0,0 +1,311 @@
+use std::cell::Cell;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+
+use crate::clock::{self, format_day};
+use crate::events::{Event, Subscriber};
+use crate::health::Forecast;
+use crate::sysfs::{self, Latency};
+use crate::{health, report};
+
+/// Aggregated battery statistics for a single (UTC) day.
+#[derive(Debug)]
+pub struct DailySummary {
+    pub day: i64,
+    pub min_capacity: Option<i8>,
+    pub max_capacity: Option<i8>,
+    pub transitions: u32,
+    pub ac_time: Duration,
+    pub plug_ins: u32,
+    pub unplugs: u32,
+    pub energy_in: i64,
+    pub energy_out: i64,
+    pub errors: u32,
+    pub io_latency: Latency,
+    pub charge_full: Option<i64>,
+    pub charge_full_design: Option<i64>,
+    last_energy: Option<i64>,
+}
+
+impl DailySummary {
+    pub fn new(day: i64) -> Self {
+        Self {
+            day,
+            min_capacity: None,
+            max_capacity: None,
+            transitions: 0,
+            ac_time: Duration::ZERO,
+            plug_ins: 0,
+            unplugs: 0,
+            energy_in: 0,
+            energy_out: 0,
+            errors: 0,
+            io_latency: Latency::default(),
+            charge_full: None,
+            charge_full_design: None,
+            last_energy: None,
+        }
+    }
+
+    /// Start the next day, carrying over the last energy reading so no flow is lost.
+    pub fn next(&self, day: i64) -> Self {
+        Self {
+            last_energy: self.last_energy,
+            ..Self::new(day)
+        }
+    }
+
+    pub fn record_capacity(&mut self, cap: i8) {
+        self.min_capacity = Some(self.min_capacity.map_or(cap, |c| c.min(cap)));
+        self.max_capacity = Some(self.max_capacity.map_or(cap, |c| c.max(cap)));
+    }
+
+    pub fn record_plug(&mut self, online: bool) {
+        if online {
+            self.plug_ins += 1;
+        } else {
+            self.unplugs += 1;
+        }
+    }
+
+    pub fn record_ac(&mut self, online: bool, elapsed: Duration) {
+        if online {
+            self.ac_time += elapsed;
+        }
+    }
+
+    /// Record an energy_now reading (µWh); deltas are split into energy in and out.
+    pub fn record_energy(&mut self, energy: i64) {
+        if let Some(last) = self.last_energy {
+            let delta = energy - last;
+            if delta > 0 {
+                self.energy_in += delta;
+            } else {
+                self.energy_out -= delta;
+            }
+        }
+        self.last_energy = Some(energy);
+    }
+
+    /// Record the latest full charge capacity and its design value (µAh).
+    pub fn record_health(&mut self, full: i64, design: i64) {
+        self.charge_full = Some(full);
+        self.charge_full_design = Some(design);
+    }
+
+    pub fn record_transition(&mut self) {
+        self.transitions += 1;
+    }
+
+    pub fn record_error(&mut self) {
+        self.errors += 1;
+    }
+}
+
+impl Display for DailySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fmt_cap = |c: Option<i8>| c.map_or("-".to_string(), |c| c.to_string());
+        write!(
+            f,
+            "day={} min_capacity={} max_capacity={} transitions={} ac_time={}s plug_ins={} unplugs={} energy_in={:.2}Wh energy_out={:.2}Wh errors={} {}",
+            format_day(self.day),
+            fmt_cap(self.min_capacity),
+            fmt_cap(self.max_capacity),
+            self.transitions,
+            self.ac_time.as_secs(),
+            self.plug_ins,
+            self.unplugs,
+            self.energy_in as f64 / 1_000_000.0,
+            self.energy_out as f64 / 1_000_000.0,
+            self.errors,
+            self.io_latency
+        )
+    }
+}
+
+/// Collects the daily summary from events, logging and exporting it when the day changes.
+pub struct SummaryRecorder {
+    summary: DailySummary,
+    last_sample: Instant,
+    /// AC state at the last sample, which held until the next one.
+    last_ac: Option<bool>,
+    reports: PathBuf,
+    replace_at: f64,
+    /// Replacement forecast from the health history, shared with the status.
+    health: Rc<Cell<Option<Forecast>>>,
+}
+
+impl SummaryRecorder {
+    /// `replace_at` is the health (percent of design capacity) at which the
+    /// battery should be replaced, used for the daily forecast.
+    /// Today's report is picked up where an earlier run left it.
+    pub fn new(reports: PathBuf, replace_at: f64) -> Self {
+        let today = clock::today();
+        let summary = match report::load_daily(&reports, today) {
+            Ok(Some(s)) => {
+                debug!("Continuing the daily summary of {}", format_day(today));
+                s
+            }
+            Ok(None) => DailySummary::new(today),
+            Err(e) => {
+                warn!("Could not load today's report, starting it over: {e:#}");
+                DailySummary::new(today)
+            }
+        };
+        let mut recorder = Self {
+            summary,
+            last_sample: Instant::now(),
+            last_ac: None,
+            reports,
+            replace_at,
+            health: Rc::default(),
+        };
+        recorder.update_health();
+        recorder
+    }
+
+    /// The battery replacement forecast, kept up to date as days pass.
+    pub fn health(&self) -> Rc<Cell<Option<Forecast>>> {
+        self.health.clone()
+    }
+
+    fn update_health(&mut self) -> Option<Forecast> {
+        match report::update_health(&self.reports, self.summary.day) {
+            Ok(points) => self.health.set(health::forecast(&points, self.replace_at)),
+            Err(e) => warn!("Could not update battery health history: {e}"),
+        }
+        self.health.get()
+    }
+
+    fn export(&mut self) {
+        self.summary.io_latency.add(&sysfs::take_latency());
+        match report::export_daily(&self.reports, &self.summary) {
+            Ok(path) => debug!("Wrote daily report to {}", path.display()),
+            Err(e) => warn!("Could not write daily report: {e}"),
+        }
+    }
+
+    /// Count the time since the last sample towards the AC state then.
+    fn observe_ac(&mut self, now: Instant, ac_online: Option<bool>) {
+        if let Some(online) = self.last_ac {
+            self.summary
+                .record_ac(online, now.duration_since(self.last_sample));
+        }
+        self.last_ac = ac_online;
+        self.last_sample = now;
+    }
+
+    fn roll_over(&mut self, day: i64) {
+        self.export();
+        info!("Daily summary: {}", self.summary);
+        self.summary = self.summary.next(day);
+        if let Some(f) = self.update_health() {
+            info!("Battery health: {f} replace_at={}%", self.replace_at);
+        }
+    }
+}
+
+impl Subscriber for SummaryRecorder {
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::CapacityRead {
+                capacity,
+                ac_online,
+                energy,
+                charge_full,
+                ..
+            } => {
+                let day = clock::today();
+                if day != self.summary.day {
+                    self.roll_over(day);
+                }
+                self.summary.record_capacity(*capacity);
+                self.observe_ac(Instant::now(), *ac_online);
+                if let Some(energy) = energy {
+                    self.summary.record_energy(*energy);
+                }
+                if let Some((full, design)) = charge_full {
+                    self.summary.record_health(*full, *design);
+                }
+            }
+            Event::TransitionApplied { .. } | Event::TransitionRecommended { .. } => {
+                self.summary.record_transition()
+            }
+            Event::AcChanged { online } => self.summary.record_plug(*online),
+            Event::Error(_) => self.summary.record_error(),
+            _ => {}
+        }
+    }
+}
+
+impl Drop for SummaryRecorder {
+    /// Keep the day so far for the next run.
+    fn drop(&mut self) {
+        self.observe_ac(Instant::now(), self.last_ac);
+        self.export();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::clock;
+    use crate::report::load_daily;
+    use crate::summary::{DailySummary, SummaryRecorder};
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn summary_tracks_extremes_and_energy() {
+        let mut s = DailySummary::new(0);
+        s.record_capacity(75);
+        s.record_capacity(70);
+        s.record_capacity(80);
+        s.record_energy(50_000_000);
+        s.record_energy(52_000_000);
+        s.record_energy(51_500_000);
+        s.record_ac(true, Duration::from_secs(60));
+        s.record_ac(false, Duration::from_secs(60));
+        s.record_transition();
+        s.record_plug(false);
+
+        assert_eq!(Some(70), s.min_capacity);
+        assert_eq!(Some(80), s.max_capacity);
+        assert_eq!(2_000_000, s.energy_in);
+        assert_eq!(500_000, s.energy_out);
+        assert_eq!(Duration::from_secs(60), s.ac_time);
+        assert_eq!(
+            "day=1970-01-01 min_capacity=70 max_capacity=80 transitions=1 ac_time=60s plug_ins=0 unplugs=1 energy_in=2.00Wh energy_out=0.50Wh errors=0 io_le_1ms=0 io_le_10ms=0 io_le_100ms=0 io_le_1s=0 io_gt_1s=0",
+            s.to_string()
+        );
+
+        let n = s.next(1);
+        assert_eq!(None, n.min_capacity);
+        assert_eq!(Some(51_500_000), n.last_energy);
+    }
+
+    #[test]
+    fn ac_time_goes_to_the_state_it_was_in_and_survives_restarts() {
+        let dir = TestDir::new("summary");
+        let mut r = SummaryRecorder::new(dir.to_path_buf(), 80.0);
+        let t = Instant::now();
+        let minute = Duration::from_secs(60);
+        r.observe_ac(t, Some(false));
+        r.observe_ac(t + minute, Some(true));
+        // Plugged in for the last two minutes, unplugged just now.
+        r.observe_ac(t + 3 * minute, Some(false));
+        assert_eq!(2 * minute, r.summary.ac_time);
+        r.last_ac = None;
+        drop(r);
+
+        let loaded = load_daily(&dir, clock::today()).unwrap().unwrap();
+        assert_eq!(2 * minute, loaded.ac_time);
+        let r = SummaryRecorder::new(dir.to_path_buf(), 80.0);
+        assert_eq!(2 * minute, r.summary.ac_time);
+        drop(r);
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::trace;
+
+/// Locations of the power_supply devices.
+#[derive(Debug)]
+struct Paths {
+    battery: PathBuf,
+    ac: PathBuf,
+}
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+/// Reads and writes slower than this are logged.
+const SLOW_IO: Duration = Duration::from_millis(100);
+
+/// Give up on reads and writes that take longer than this.
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bounds of the latency histogram buckets, the last bucket is unbounded.
+const BUCKETS: [Duration; 4] = [
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// Attributes with an operation that timed out and hasn't returned yet.
+static HUNG: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+static COUNTS: [AtomicU64; 5] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// Histogram of sysfs operation latencies.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Latency {
+    pub le_1ms: u64,
+    pub le_10ms: u64,
+    pub le_100ms: u64,
+    pub le_1s: u64,
+    pub gt_1s: u64,
+}
+
+impl Latency {
+    /// Add the counts of `other`.
+    pub fn add(&mut self, other: &Latency) {
+        self.le_1ms += other.le_1ms;
+        self.le_10ms += other.le_10ms;
+        self.le_100ms += other.le_100ms;
+        self.le_1s += other.le_1s;
+        self.gt_1s += other.gt_1s;
+    }
+}
+
+impl Display for Latency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "io_le_1ms={} io_le_10ms={} io_le_100ms={} io_le_1s={} io_gt_1s={}",
+            self.le_1ms, self.le_10ms, self.le_100ms, self.le_1s, self.gt_1s
+        )
+    }
+}
+
+/// Set the battery and AC device directories, optionally prefixed with
+/// `root`, e.g. where the host's /sys is bind-mounted inside a container.
+/// Only the first call has any effect.
+pub fn configure(root: Option<&Path>, battery: &Path, ac: &Path) {
+    let prefix = |p: &Path| match root {
+        Some(root) => root.join(p.strip_prefix("/").unwrap_or(p)),
+        None => p.to_path_buf(),
+    };
+    let _ = PATHS.set(Paths {
+        battery: prefix(battery),
+        ac: prefix(ac),
+    });
+}
+
+fn paths() -> &'static Paths {
+    PATHS.get_or_init(|| Paths {
+        battery: PathBuf::from("/sys/class/power_supply/macsmc-battery"),
+        ac: PathBuf::from("/sys/class/power_supply/macsmc-ac"),
+    })
+}
+
+/// Path of a battery attribute.
+pub fn battery(attr: &str) -> PathBuf {
+    paths().battery.join(attr)
+}
+
+/// Path of an AC adapter attribute.
+pub fn ac(attr: &str) -> PathBuf {
+    paths().ac.join(attr)
+}
+
+/// Whether the configured battery, or any other power_supply device of type
+/// `Battery` next to it, exists.
+pub fn battery_present() -> bool {
+    let battery = &paths().battery;
+    if battery.exists() {
+        return true;
+    }
+    let Some(Ok(entries)) = battery.parent().map(std::fs::read_dir) else {
+        return false;
+    };
+    entries.flatten().any(|e| {
+        std::fs::read_to_string(e.path().join("type")).is_ok_and(|t| t.trim() == "Battery")
+    })
+}
+
+/// Return the latency histogram collected since the last call, and reset it.
+pub fn take_latency() -> Latency {
+    let take = |i: usize| COUNTS[i].swap(0, Ordering::Relaxed);
+    Latency {
+        le_1ms: take(0),
+        le_10ms: take(1),
+        le_100ms: take(2),
+        le_1s: take(3),
+        gt_1s: take(4),
+    }
+}
+
+fn record(op: &str, path: &Path, elapsed: Duration) {
+    let bucket = BUCKETS
+        .iter()
+        .position(|b| elapsed <= *b)
+        .unwrap_or(BUCKETS.len());
+    COUNTS[bucket].fetch_add(1, Ordering::Relaxed);
+    if elapsed > SLOW_IO {
+        debug!("Slow sysfs {op} of {}: {elapsed:?}", path.display());
+    }
+}
+
+/// Run `f` on `path` on a separate thread, returning a `TimedOut` error if it
+/// doesn't finish in time. A hung SMC transaction then only blocks that
+/// thread, which is left behind, and `path` isn't touched again until it
+/// returns, so at most one thread is stuck on each attribute.
+fn with_timeout<T, F>(path: &Path, timeout: Duration, f: F) -> std::io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+{
+    let hung = || HUNG.lock().unwrap_or_else(PoisonError::into_inner);
+    if hung().contains(path) {
+        return Err(Error::new(
+            ErrorKind::TimedOut,
+            format!(
+                "an earlier operation on {} still hasn't returned",
+                path.display()
+            ),
+        ));
+    }
+    let (tx, rx) = mpsc::channel();
+    let p = path.to_path_buf();
+    thread::spawn(move || {
+        let res = f();
+        // Locked while sending, so the caller can't give up in between.
+        let mut hung = hung();
+        if tx.send(res).is_err() {
+            hung.remove(&p);
+        }
+    });
+    if let Ok(res) = rx.recv_timeout(timeout) {
+        return res;
+    }
+    let mut hung = hung();
+    if let Ok(res) = rx.try_recv() {
+        return res;
+    }
+    hung.insert(path.to_path_buf());
+    drop(rx);
+    Err(Error::new(
+        ErrorKind::TimedOut,
+        format!("sysfs operation timed out after {timeout:?}"),
+    ))
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> std::io::Result<String> {
+    let path = path.as_ref().to_path_buf();
+    let start = Instant::now();
+    let p = path.clone();
+    let res = with_timeout(&path, IO_TIMEOUT, move || fs::read_to_string(p));
+    record("read", &path, start.elapsed());
+    trace::record('r', &path, res.as_deref());
+    res
+}
+
+pub fn write<P: AsRef<Path>>(path: P, contents: &str) -> std::io::Result<()> {
+    let path = path.as_ref().to_path_buf();
+    let contents = contents.to_string();
+    let start = Instant::now();
+    let p = path.clone();
+    let c = contents.clone();
+    let res = with_timeout(&path, IO_TIMEOUT, move || fs::write(p, c));
+    record("write", &path, start.elapsed());
+    trace::record('w', &path, res.as_ref().map(|_| contents.as_str()));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+    use std::path::Path;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use crate::sysfs::{record, take_latency, with_timeout, Latency};
+
+    #[test]
+    fn latencies_land_in_buckets() {
+        let p = Path::new("/dev/null");
+        record("read", p, Duration::from_micros(500));
+        record("read", p, Duration::from_millis(1));
+        record("read", p, Duration::from_millis(50));
+        record("write", p, Duration::from_secs(3));
+
+        let expected = Latency {
+            le_1ms: 2,
+            le_10ms: 0,
+            le_100ms: 1,
+            le_1s: 0,
+            gt_1s: 1,
+        };
+        assert_eq!(expected, take_latency());
+        assert_eq!(Latency::default(), take_latency());
+    }
+
+    #[test]
+    fn hung_operations_time_out() {
+        let p = Path::new("/hung");
+        let res = with_timeout(p, Duration::from_millis(10), || {
+            sleep(Duration::from_millis(200));
+            Ok(())
+        });
+        assert_eq!(ErrorKind::TimedOut, res.unwrap_err().kind());
+
+        // Not retried while the first one is still stuck.
+        let res = with_timeout(p, Duration::from_secs(1), || Ok(42));
+        assert_eq!(ErrorKind::TimedOut, res.unwrap_err().kind());
+        let res = with_timeout(Path::new("/other"), Duration::from_secs(1), || Ok(42));
+        assert_eq!(42, res.unwrap());
+
+        sleep(Duration::from_millis(400));
+        let res = with_timeout(p, Duration::from_secs(1), || Ok(42));
+        assert_eq!(42, res.unwrap());
+    }
+}
@@ -0,0 +1,58 @@
+use log::info;
+
+/// Degrees below the limit the battery has to cool to before charging resumes.
+pub const HYSTERESIS: i32 = 3;
+
+/// Keeps charging inhibited while the battery is hotter than a limit.
+#[derive(Debug)]
+pub struct ThermalGuard {
+    /// In tenths of a degree Celsius, like the power_supply `temp` attribute.
+    limit: i32,
+    hot: bool,
+}
+
+impl ThermalGuard {
+    /// `limit` in degrees Celsius.
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit: limit as i32 * 10,
+            hot: false,
+        }
+    }
+
+    /// Returns true while charging should be inhibited. The previous state is
+    /// kept when the temperature can't be read.
+    pub fn observe(&mut self, temp: Option<i32>) -> bool {
+        let Some(t) = temp else {
+            return self.hot;
+        };
+        let c = f64::from(t) / 10.0;
+        if !self.hot && t > self.limit {
+            info!(
+                "Battery is at {c:.1}°C, above {}°C. Not charging until it cools down",
+                self.limit / 10
+            );
+            self.hot = true;
+        } else if self.hot && t <= self.limit - HYSTERESIS * 10 {
+            info!("Battery has cooled down to {c:.1}°C, charging may resume");
+            self.hot = false;
+        }
+        self.hot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::thermal::ThermalGuard;
+
+    #[test]
+    fn inhibits_until_cooled_down() {
+        let mut g = ThermalGuard::new(40);
+        assert!(!g.observe(Some(400)));
+        assert!(g.observe(Some(401)));
+        assert!(g.observe(None));
+        assert!(g.observe(Some(380)));
+        assert!(!g.observe(Some(370)));
+        assert!(!g.observe(None));
+    }
+}
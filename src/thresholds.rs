@@ -0,0 +1,76 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use anyhow::anyhow;
+
+use crate::state::RequestFile;
+
+const FILE: RequestFile = RequestFile::new("thresholds");
+
+/// Where the thresholds in use come from, in order of precedence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Storage,
+    Requested,
+    Profile(String),
+    /// The desktop's battery settings, through UPower.
+    Desktop,
+    Configured,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Storage => write!(f, "storage mode"),
+            Source::Requested => write!(f, "requested"),
+            Source::Profile(name) => write!(f, "profile {name}"),
+            Source::Desktop => write!(f, "from the desktop battery settings"),
+            Source::Configured => write!(f, "configured"),
+        }
+    }
+}
+
+/// Ask the running daemon to use these thresholds instead of the configured ones.
+pub fn request(dir: &Path, low: i8, high: i8) -> Result<(), anyhow::Error> {
+    if !(0..=100).contains(&low) || !(0..=100).contains(&high) || low >= high {
+        return Err(anyhow!(
+            "Thresholds must be between 0 and 100 with low below high, got {low} {high}"
+        ));
+    }
+    FILE.write(dir, &format!("{low} {high}"))
+}
+
+/// The currently requested thresholds, if any.
+pub fn pending(dir: &Path) -> Result<Option<(i8, i8)>, anyhow::Error> {
+    FILE.read(dir)?.map(|s| parse(&s)).transpose()
+}
+
+pub fn clear(dir: &Path) -> Result<(), anyhow::Error> {
+    FILE.clear(dir)
+}
+
+fn parse(s: &str) -> Result<(i8, i8), anyhow::Error> {
+    match s.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [low, high] => Ok((low.parse()?, high.parse()?)),
+        _ => Err(anyhow!("Invalid thresholds request {s:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::thresholds::{parse, request};
+
+    #[test]
+    fn parse_and_validate() {
+        assert_eq!((60, 80), parse("60 80").unwrap());
+        assert!(parse("60").is_err());
+        assert!(parse("60 high").is_err());
+
+        // Rejected before anything is written.
+        let dir = Path::new("/nonexistent");
+        assert!(request(dir, 80, 60).is_err());
+        assert!(request(dir, 60, 101).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{calc_behaviour, clock, policy_reason, ChargeBehaviour};
+
+static TRACE: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// A single raw sysfs operation, one JSON object per line in the trace file.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Milliseconds since the epoch.
+    pub t: u128,
+    /// `r` for reads, `w` for writes.
+    pub op: char,
+    pub path: PathBuf,
+    /// Contents read or written, absent if the operation failed.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+}
+
+/// Record every sysfs read and write to `path` from now on, appending to it.
+pub fn start(path: &Path) -> Result<(), anyhow::Error> {
+    let f = OpenOptions::new().create(true).append(true).open(path)?;
+    let _ = TRACE.set(Mutex::new(f));
+    Ok(())
+}
+
+/// Add an operation to the trace, if one is being recorded.
+pub fn record(op: char, path: &Path, result: Result<&str, &std::io::Error>) {
+    let Some(trace) = TRACE.get() else {
+        return;
+    };
+    let entry = Entry {
+        t: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis()),
+        op,
+        path: path.to_path_buf(),
+        value: result.ok().map(|v| v.trim_end().to_string()),
+        error: result.err().map(|e| e.to_string()),
+    };
+    if let (Ok(mut f), Ok(line)) = (trace.lock(), serde_json::to_string(&entry)) {
+        let _ = writeln!(f, "{line}");
+    }
+}
+
+/// Load a recorded trace.
+pub fn load(path: &Path) -> Result<Vec<Entry>, anyhow::Error> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .map(|l| Ok(serde_json::from_str(&l?)?))
+        .collect()
+}
+
+/// Run the policy over the capacity and AC readings of a trace, returning
+/// the behaviour changes it would make with these thresholds.
+pub fn replay(entries: &[Entry], low: i8, high: i8) -> Vec<String> {
+    let mut behaviour = None;
+    let mut ac_online = None;
+    let mut changes = Vec::new();
+    for e in entries {
+        let (Some(attr), Some(value)) = (e.path.file_name(), &e.value) else {
+            continue;
+        };
+        match (e.op, attr.to_str()) {
+            ('r', Some("charge_behaviour")) if behaviour.is_none() => {
+                behaviour = value.parse::<ChargeBehaviour>().ok();
+            }
+            ('r', Some("online")) => ac_online = Some(value == "1"),
+            ('r', Some("capacity")) => {
+                let (Some(be), Ok(cap)) = (behaviour, value.parse::<i8>()) else {
+                    continue;
+                };
+                let new = calc_behaviour(cap, &be, low, high);
+                if new != be {
+                    changes.push(format!(
+                        "{} capacity={cap} ac_online={} {be} -> {new} ({})",
+                        clock::format_timestamp((e.t / 1000) as u64),
+                        ac_online.map_or("-".to_string(), |o| o.to_string()),
+                        policy_reason(cap, low, high)
+                    ));
+                    behaviour = Some(new);
+                }
+            }
+            _ => {}
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::trace::{replay, Entry};
+
+    #[test]
+    fn entries_are_compact_json_lines() {
+        let read = Entry {
+            t: 1000,
+            op: 'r',
+            path: PathBuf::from("/sys/class/power_supply/macsmc-battery/capacity"),
+            value: Some("80".to_string()),
+            error: None,
+        };
+        let line = serde_json::to_string(&read).unwrap();
+        assert_eq!(
+            r#"{"t":1000,"op":"r","path":"/sys/class/power_supply/macsmc-battery/capacity","value":"80"}"#,
+            line
+        );
+        assert_eq!(read, serde_json::from_str(&line).unwrap());
+
+        let failed: Entry =
+            serde_json::from_str(r#"{"t":5,"op":"w","path":"/x","error":"busy"}"#).unwrap();
+        assert_eq!(None, failed.value);
+        assert_eq!(Some("busy".to_string()), failed.error);
+    }
+
+    #[test]
+    fn replay_applies_policy_to_readings() {
+        let read = |t, attr: &str, value: &str| Entry {
+            t,
+            op: 'r',
+            path: PathBuf::from("/sys/class/power_supply/macsmc-battery").join(attr),
+            value: Some(value.to_string()),
+            error: None,
+        };
+        let entries = vec![
+            read(0, "charge_behaviour", "auto"),
+            read(0, "capacity", "79"),
+            read(0, "online", "1"),
+            read(60_000, "capacity", "81"),
+            read(120_000, "capacity", "80"),
+            read(180_000, "capacity", "80"),
+        ];
+        assert_eq!(
+            vec![
+                "1970-01-01T00:01:00Z capacity=81 ac_online=true auto -> force-discharge (above high threshold)".to_string(),
+                "1970-01-01T00:02:00Z capacity=80 ac_online=true force-discharge -> inhibit-charge (within thresholds)".to_string(),
+            ],
+            replay(&entries, 70, 80)
+        );
+    }
+}
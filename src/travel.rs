@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use crate::state::RequestFile;
+
+/// Percentage points discharged on battery after which travel mode ends.
+pub const USAGE_THRESHOLD: i8 = 20;
+
+const FILE: RequestFile = RequestFile::new("travel");
+
+pub fn enable(dir: &Path) -> Result<(), anyhow::Error> {
+    FILE.write(dir, "")
+}
+
+pub fn disable(dir: &Path) -> Result<(), anyhow::Error> {
+    FILE.clear(dir)
+}
+
+pub fn active(dir: &Path) -> bool {
+    FILE.exists(dir)
+}
+
+/// Tracks battery usage while unplugged during travel mode.
+#[derive(Debug, Default)]
+pub struct Trip {
+    /// Highest capacity seen since AC was unplugged.
+    peak: Option<i8>,
+}
+
+impl Trip {
+    /// Returns how much was discharged on battery once it reaches the usage
+    /// threshold, meaning travel mode should end.
+    pub fn observe(&mut self, cap: i8, ac_online: Option<bool>) -> Option<i8> {
+        match ac_online {
+            Some(false) => {
+                let peak = self.peak.map_or(cap, |p| p.max(cap));
+                self.peak = Some(peak);
+                (peak - cap >= USAGE_THRESHOLD).then_some(peak - cap)
+            }
+            Some(true) => {
+                self.peak = None;
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::travel::Trip;
+
+    #[test]
+    fn trip_ends_after_significant_battery_use() {
+        let mut t = Trip::default();
+        assert_eq!(None, t.observe(100, Some(true)));
+        assert_eq!(None, t.observe(100, Some(false)));
+        assert_eq!(None, t.observe(85, Some(false)));
+        // Plugging back in starts counting again.
+        assert_eq!(None, t.observe(90, Some(true)));
+        assert_eq!(None, t.observe(90, Some(false)));
+        assert_eq!(None, t.observe(71, Some(false)));
+        assert_eq!(Some(20), t.observe(70, Some(false)));
+    }
+}
@@ -0,0 +1,119 @@
+use std::io::{Error, ErrorKind};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use log::warn;
+
+use crate::wake::Wake;
+
+/// Subscribe to kernel uevents, sending a [`Wake::Uevent`] on `tx` for every
+/// power_supply change, and a [`Wake::Reregistered`] when one is added.
+pub fn subscribe(tx: Sender<Wake>) -> Result<(), anyhow::Error> {
+    // SAFETY: plain socket creation, the result is checked before use.
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        )
+    };
+    if fd < 0 {
+        return Err(Error::last_os_error().into());
+    }
+    // SAFETY: fd is a freshly created socket nothing else owns.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    // SAFETY: sockaddr_nl is plain data, all zeroes is a valid value.
+    let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    // The kernel's uevent multicast group.
+    addr.nl_groups = 1;
+    // SAFETY: addr is a valid sockaddr_nl and the length matches it.
+    let res = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if res < 0 {
+        return Err(Error::last_os_error().into());
+    }
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            // SAFETY: buf is valid for writes of its length.
+            let n = unsafe { libc::recv(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n < 0 {
+                let e = Error::last_os_error();
+                if e.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                warn!("Stopped listening for uevents: {e}");
+                return;
+            }
+            if let Some(w) = classify(&buf[..n as usize]) {
+                if tx.send(w).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Whether a uevent message, `action@devpath` followed by NUL-separated
+/// `KEY=value` pairs, is about a power supply.
+fn is_power_supply(msg: &[u8]) -> bool {
+    msg.split(|b| *b == 0)
+        .any(|field| field == b"SUBSYSTEM=power_supply")
+}
+
+/// What a uevent means for the daemon, if it's about a power supply. Removals
+/// are ignored, the device can't be used until it's added again.
+fn classify(msg: &[u8]) -> Option<Wake> {
+    if !is_power_supply(msg) {
+        return None;
+    }
+    if msg.starts_with(b"add@") {
+        Some(Wake::Reregistered)
+    } else if msg.starts_with(b"remove@") {
+        None
+    } else {
+        Some(Wake::Uevent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::uevent::{classify, is_power_supply};
+    use crate::wake::Wake;
+
+    #[test]
+    fn match_power_supply_events() {
+        assert!(is_power_supply(
+            b"change@/devices/platform/macsmc-battery\0ACTION=change\0SUBSYSTEM=power_supply\0POWER_SUPPLY_CAPACITY=80\0"
+        ));
+        assert!(!is_power_supply(
+            b"add@/devices/virtual/net/lo\0ACTION=add\0SUBSYSTEM=net\0"
+        ));
+    }
+
+    #[test]
+    fn re_registration_is_told_apart() {
+        let ev = |action: &str| {
+            format!(
+                "{action}@/devices/platform/macsmc-ac\0ACTION={action}\0SUBSYSTEM=power_supply\0"
+            )
+        };
+        assert_eq!(Some(Wake::Uevent), classify(ev("change").as_bytes()));
+        assert_eq!(Some(Wake::Reregistered), classify(ev("add").as_bytes()));
+        assert_eq!(None, classify(ev("remove").as_bytes()));
+        assert_eq!(
+            None,
+            classify(b"add@/devices/virtual/net/lo\0ACTION=add\0SUBSYSTEM=net\0")
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use std::process::Command;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+
+/// Whether updates are pending according to `check`, a shell command that
+/// exits 0 if they are. Without a check they are assumed to be.
+pub fn updates_pending(check: Option<&str>) -> Result<bool, anyhow::Error> {
+    let Some(check) = check else {
+        return Ok(true);
+    };
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(check)
+        .status()
+        .with_context(|| format!("Could not run {check:?}"))?;
+    Ok(status.success())
+}
+
+/// Wait until `capacity` reports at least `level`, polling every `poll`.
+pub fn wait_for(
+    level: i8,
+    timeout: Duration,
+    poll: Duration,
+    mut capacity: impl FnMut() -> Result<i8, anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let start = Instant::now();
+    loop {
+        let cap = capacity()?;
+        if cap >= level {
+            return Ok(());
+        }
+        if start.elapsed() >= timeout {
+            return Err(anyhow!(
+                "Battery still at {cap}% after {} minutes, below {level}%",
+                timeout.as_secs() / 60
+            ));
+        }
+        sleep(poll);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::update::{updates_pending, wait_for};
+
+    #[test]
+    fn check_command_decides_pending() {
+        assert!(updates_pending(None).unwrap());
+        assert!(updates_pending(Some("true")).unwrap());
+        assert!(!updates_pending(Some("exit 1")).unwrap());
+    }
+
+    #[test]
+    fn waits_until_level_or_timeout() {
+        let mut readings = [40, 45, 50].into_iter();
+        let next = || Ok(readings.next().unwrap());
+        let poll = Duration::from_millis(1);
+        assert!(wait_for(50, Duration::from_secs(1), poll, next).is_ok());
+        assert!(wait_for(50, Duration::ZERO, poll, || Ok(40)).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use crate::sysfs;
+
+/// The kernel's charge_control start and end thresholds, which UPower (and
+/// the GNOME/KDE battery settings through it) writes to limit charging.
+/// `None` if the battery has no end threshold.
+pub fn kernel_thresholds() -> Option<(Option<i8>, i8)> {
+    let read = |attr| {
+        sysfs::read(sysfs::battery(attr))
+            .ok()
+            .and_then(|s| s.trim().parse::<i8>().ok())
+    };
+    let end = read("charge_control_end_threshold")?;
+    Some((read("charge_control_start_threshold"), end))
+}
+
+/// Thresholds to use given the kernel's: an end threshold of 100 means the
+/// desktop limit is off, so charging is allowed to full.
+pub fn thresholds((start, end): (Option<i8>, i8), (low, high): (i8, i8)) -> (i8, i8) {
+    match start {
+        _ if end >= 100 => (low, 100),
+        Some(start) if start < end => (start, end),
+        _ if end <= 0 => (low, high),
+        _ => (low.min(end - 1), end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::upower::thresholds;
+
+    #[test]
+    fn follow_kernel_thresholds() {
+        assert_eq!((75, 80), thresholds((Some(75), 80), (70, 80)));
+        assert_eq!((70, 100), thresholds((Some(0), 100), (70, 80)));
+        assert_eq!((60, 75), thresholds((None, 75), (60, 80)));
+        assert_eq!((59, 60), thresholds((Some(60), 60), (70, 80)));
+        assert_eq!((70, 80), thresholds((Some(0), 0), (70, 80)));
+    }
+}
@@ -0,0 +1,58 @@
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR2};
+use signal_hook::iterator::Signals;
+
+/// Why the daemon was woken before its next poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wake {
+    /// A power_supply uevent.
+    Uevent,
+    /// A power_supply device was added, e.g. after a dock reset it, and may
+    /// have lost the charge behaviour.
+    Reregistered,
+    /// The system resumed from suspend, signalled with SIGUSR2 by the
+    /// systemd-sleep hook. Commands leaving a request signal it too.
+    Resume,
+    /// SIGTERM or SIGINT, asking the daemon to exit.
+    Stop,
+    /// A client asked for something, e.g. over MQTT.
+    Request,
+}
+
+/// Wake the daemon running as the systemd service with SIGUSR2, as the sleep
+/// hook does. Returns whether that worked.
+pub fn signal_daemon() -> bool {
+    Command::new("systemctl")
+        .args([
+            "kill",
+            "--kill-whom=main",
+            "--signal=SIGUSR2",
+            "macsmc-charged.service",
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Send a [`Wake::Resume`] whenever SIGUSR2 is received, and a
+/// [`Wake::Stop`] for SIGTERM and SIGINT.
+pub fn on_signals(tx: Sender<Wake>) -> Result<(), anyhow::Error> {
+    let mut signals = Signals::new([SIGUSR2, SIGTERM, SIGINT])?;
+    thread::spawn(move || {
+        for sig in signals.forever() {
+            let wake = if sig == SIGUSR2 {
+                Wake::Resume
+            } else {
+                Wake::Stop
+            };
+            if tx.send(wake).is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}